@@ -0,0 +1,106 @@
+use core::ptr::NonNull;
+
+use allocator::{
+    fixed_size_block,
+    linked_list::{self, Strategy},
+    workload::{Driver, Workload},
+    Allocator,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const HEAP_SIZE: usize = 1 << 20;
+const STEPS: usize = 10_000;
+const SEED: u64 = 0x5eed;
+
+/// Returns a fresh allocator of the named kind over `heap`.
+fn allocator(kind: &str, heap: &mut [u64]) -> Box<dyn Heap> {
+    let region = NonNull::from(unsafe { heap.align_to_mut::<u8>().1 });
+    unsafe {
+        match kind {
+            "first_fit" => {
+                let mut alloc = linked_list::Allocator::coalescing();
+                alloc.add_free_region(region);
+                Box::new(alloc)
+            }
+            "best_fit" => {
+                let mut alloc = linked_list::Allocator::builder()
+                    .coalesce(true)
+                    .strategy(Strategy::BestFit)
+                    .build();
+                alloc.add_free_region(region);
+                Box::new(alloc)
+            }
+            "slab" => {
+                let mut alloc = fixed_size_block::Allocator::new();
+                alloc.add_free_region(region);
+                Box::new(alloc)
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// An allocator whose fragmentation can be measured.
+trait Heap {
+    fn alloc(&mut self) -> &mut dyn Allocator;
+    fn free_blocks(&self) -> usize;
+}
+
+impl Heap for linked_list::Allocator {
+    fn alloc(&mut self) -> &mut dyn Allocator {
+        self
+    }
+
+    fn free_blocks(&self) -> usize {
+        self.free_block_count()
+    }
+}
+
+impl Heap for fixed_size_block::Allocator {
+    fn alloc(&mut self) -> &mut dyn Allocator {
+        self
+    }
+
+    fn free_blocks(&self) -> usize {
+        self.fallback().free_block_count()
+    }
+}
+
+fn workloads(c: &mut Criterion) {
+    let mut heap = vec![0u64; HEAP_SIZE / 8];
+    for workload in [
+        Workload::SmallSameSize,
+        Workload::MixedSizes,
+        Workload::Churn,
+    ] {
+        let mut group = c.benchmark_group(format!("{workload:?}"));
+        group.throughput(Throughput::Elements(STEPS.try_into().unwrap()));
+        for kind in ["first_fit", "best_fit", "slab"] {
+            // fragmentation left behind by one run, which is the same for
+            // every run with the same seed
+            let mut alloc = allocator(kind, &mut heap);
+            let mut driver = Driver::new(workload, SEED);
+            let report = unsafe { driver.run(alloc.alloc(), STEPS) };
+            println!(
+                "{workload:?}/{kind}: {report:?}, {} free blocks",
+                alloc.free_blocks()
+            );
+            drop(alloc);
+
+            group.bench_function(BenchmarkId::from_parameter(kind), |b| {
+                b.iter(|| {
+                    let mut alloc = allocator(kind, &mut heap);
+                    let mut driver = Driver::new(workload, SEED);
+                    unsafe {
+                        driver.run(alloc.alloc(), STEPS);
+                        driver.free_all(alloc.alloc());
+                    }
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, workloads);
+criterion_main!(benches);
@@ -1,28 +1,255 @@
 use core::{
     alloc::Layout,
-    ptr::{slice_from_raw_parts_mut, NonNull},
+    array,
+    marker::PhantomData,
+    mem,
+    ops::Range,
+    ptr::{self, slice_from_raw_parts_mut, NonNull},
+    slice,
 };
 
 use ptr_ext::PtrExt;
 
-pub struct Allocator {
+/// A bump allocator with exclusive use of its region for `'a`.
+// laid out in field order so that its size can be checked against the fields
+#[repr(C)]
+pub struct Allocator<'a> {
     region: NonNull<[u8]>,
     tip: *mut u8,
     allocations: u64,
+    auto_reset: bool,
+    scrub_on_drop: bool,
+    // whether the region is known to be valid and unused by anything else for
+    // `'a`, which the byte accessors rely on
+    exclusive: bool,
+    _region: PhantomData<&'a mut [u8]>,
 }
 
-impl Allocator {
-    pub fn new(region: NonNull<[u8]>) -> Allocator {
+impl<'a> Allocator<'a> {
+    /// Creates an allocator handing out memory from `region`. Nothing
+    /// guarantees that the region stays valid, so its bytes cannot be
+    /// accessed through [`as_bytes`](Allocator::as_bytes).
+    pub fn new(region: NonNull<[u8]>) -> Allocator<'a> {
         Allocator {
             region,
             tip: region.as_mut_ptr(),
             allocations: 0,
+            auto_reset: true,
+            scrub_on_drop: false,
+            exclusive: false,
+            _region: PhantomData,
         }
     }
+
+    /// Like [`new`](Allocator::new), but the bytes of the region can also be
+    /// accessed through [`as_bytes`](Allocator::as_bytes).
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that
+    /// `region` is valid and unused by anything else for `'a`.
+    pub unsafe fn from_raw(region: NonNull<[u8]>) -> Allocator<'a> {
+        let mut alloc = Allocator::new(region);
+        alloc.exclusive = true;
+        alloc
+    }
+
+    /// Creates an allocator handing out memory from `slice`, which it
+    /// borrows for as long as it lives.
+    pub fn from_slice(slice: &'a mut [u8]) -> Allocator<'a> {
+        unsafe { Allocator::from_raw(NonNull::from(slice)) }
+    }
+
+    /// Creates an allocator for an arena reused across frames, which only
+    /// rewinds at a [`frame_boundary`](Allocator::frame_boundary) rather than
+    /// whenever its last allocation is freed.
+    pub fn per_frame(region: NonNull<[u8]>) -> Allocator<'a> {
+        let mut alloc = Allocator::new(region);
+        alloc.auto_reset = false;
+        alloc
+    }
+
+    /// Returns the whole region, including both live and free memory, for
+    /// inspection, or `None` if the allocator was made by
+    /// [`new`](Allocator::new).
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        self.exclusive
+            .then(|| unsafe { slice::from_raw_parts(self.region.as_mut_ptr(), self.region.len()) })
+    }
+
+    /// Returns the whole region, including both live and free memory, or
+    /// `None` if the allocator was made by [`new`](Allocator::new).
+    pub fn as_bytes_mut(&mut self) -> Option<&mut [u8]> {
+        self.exclusive.then(|| unsafe {
+            slice::from_raw_parts_mut(self.region.as_mut_ptr(), self.region.len())
+        })
+    }
+
+    /// Returns whether the allocator rewinds as soon as its last allocation is
+    /// freed.
+    pub fn auto_reset(&self) -> bool {
+        self.auto_reset
+    }
+
+    /// Returns whether the whole region is zeroed when the allocator is
+    /// dropped.
+    pub fn scrub_on_drop(&self) -> bool {
+        self.scrub_on_drop && self.exclusive
+    }
+
+    /// Rewinds the allocator to the start of its region if every allocation
+    /// has been freed, and otherwise does nothing.
+    pub fn frame_boundary(&mut self) {
+        if self.allocations == 0 {
+            self.tip = self.region.as_mut_ptr();
+        }
+    }
+
+    /// Rewinds the allocator to `reserved_bytes` past the start of its
+    /// region, keeping the allocations in that prefix, such as long-lived
+    /// data ahead of per-frame scratch memory. Panics if the next allocation
+    /// would already start below that point.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that every
+    /// allocation past the prefix has been freed, so that the allocations
+    /// still counted as outstanding all lie within it.
+    pub unsafe fn reset_to(&mut self, reserved_bytes: usize) {
+        let offset = self.tip.addr() - self.region.addr().get();
+        assert!(reserved_bytes <= offset, "reserved prefix past the tip");
+        self.tip = self
+            .region
+            .as_mut_ptr()
+            .map_addr(|addr| addr + reserved_bytes);
+    }
+
+    /// Moves the allocator to `new_region`, which must be the same size as
+    /// the current region, keeping the offset of the next allocation.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that
+    /// `new_region` is valid and unused by anything else for `'a`, holds the
+    /// contents of the current region, and must rebase
+    /// any outstanding allocations by the same amount themselves.
+    pub unsafe fn rebase(&mut self, new_region: NonNull<[u8]>) {
+        assert_eq!(new_region.len(), self.region.len());
+        let offset = self.tip.addr() - self.region.addr().get();
+        self.tip = new_region.as_mut_ptr().map_addr(|addr| addr + offset);
+        self.region = new_region;
+    }
+
+    /// Returns the number of bytes between the next allocation and the end of
+    /// the region.
+    pub fn remaining(&self) -> usize {
+        self.region_range().end - self.tip.addr()
+    }
+
+    /// Returns whether there are no outstanding allocations.
+    pub fn is_empty(&self) -> bool {
+        self.allocations == 0
+    }
+
+    /// Returns whether no allocation of a non-zero size can succeed.
+    pub fn is_full(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Returns the range of addresses managed by this allocator.
+    pub fn region_range(&self) -> Range<usize> {
+        let start = self.region.addr().get();
+        start..start + self.region.len()
+    }
+}
+
+impl Drop for Allocator<'_> {
+    fn drop(&mut self) {
+        if self.scrub_on_drop() {
+            // volatile so that the writes are not optimized away as dead
+            // stores to memory that is no longer used
+            let start = self.region.as_mut_ptr();
+            for i in 0..self.region.len() {
+                unsafe { ptr::write_volatile(start.wrapping_add(i), 0) };
+            }
+        }
+    }
+}
+
+impl<'a> From<&'a mut [u8]> for Allocator<'a> {
+    fn from(slice: &'a mut [u8]) -> Allocator<'a> {
+        Allocator::from_slice(slice)
+    }
+}
+
+/// Configures an [`Allocator`] option by option.
+pub struct Builder<'a>(Allocator<'a>);
+
+impl<'a> Builder<'a> {
+    pub fn new(region: NonNull<[u8]>) -> Builder<'a> {
+        Builder(Allocator::new(region))
+    }
+
+    pub fn from_slice(slice: &'a mut [u8]) -> Builder<'a> {
+        Builder(Allocator::from_slice(slice))
+    }
+
+    /// Rewind as soon as the last allocation is freed, which is the default.
+    /// Otherwise, see [`Allocator::per_frame`].
+    pub fn auto_reset(mut self, auto_reset: bool) -> Builder<'a> {
+        self.0.auto_reset = auto_reset;
+        self
+    }
+
+    /// Zero the whole region when the allocator is dropped, for memory that
+    /// held secrets. Off by default. Only an allocator over a region it
+    /// borrows, see [`Builder::from_slice`], is scrubbed, as the region of
+    /// one made by [`Builder::new`] may still be used by something else.
+    pub fn scrub_on_drop(mut self, scrub_on_drop: bool) -> Builder<'a> {
+        self.0.scrub_on_drop = scrub_on_drop;
+        self
+    }
+
+    pub fn build(self) -> Allocator<'a> {
+        self.0
+    }
+}
+
+/// Splits `region` into `K` equally sized, `usize`-aligned chunks and returns
+/// a bump allocator for each. Whatever is left over after the split is
+/// unused.
+///
+/// If `region` is too small to give every chunk at least one aligned word,
+/// every allocator gets an empty region and all allocations from it fail.
+pub fn split_into<'a, const K: usize>(region: NonNull<[u8]>) -> [Allocator<'a>; K] {
+    const ALIGN: usize = mem::align_of::<usize>();
+    let start = region.as_mut_ptr().try_align_up(ALIGN);
+    let chunk_size = match start {
+        Some(start) if K > 0 => {
+            let lead = start.addr() - region.addr().get();
+            (region.len().saturating_sub(lead) / K) & !(ALIGN - 1)
+        }
+        _ => 0,
+    };
+    let start = start.unwrap_or(region.as_mut_ptr());
+    array::from_fn(|i| {
+        let chunk = start.wrapping_add(i * chunk_size);
+        Allocator::new(NonNull::new(slice_from_raw_parts_mut(chunk, chunk_size)).unwrap())
+    })
+}
+
+/// Returns the smallest region size from which a bump allocator is
+/// guaranteed to satisfy an allocation of `layout`, whatever the alignment of
+/// the region's start.
+pub const fn min_bump_region_size(layout: Layout) -> usize {
+    layout.size() + layout.align() - 1
 }
 
-unsafe impl super::Allocator for Allocator {
+unsafe impl super::Allocator for Allocator<'_> {
     unsafe fn alloc(&mut self, layout: Layout) -> Option<NonNull<[u8]>> {
+        if layout.size() == 0 {
+            return Some(crate::dangling(layout));
+        }
         let alloc_start = self.tip.try_align_up(layout.align())?;
         let alloc_end = alloc_start.with_addr(alloc_start.addr().checked_add(layout.size())?);
         if alloc_end.addr() > self.region.addr().get() + self.region.len() {
@@ -33,10 +260,143 @@ unsafe impl super::Allocator for Allocator {
         NonNull::new(slice_from_raw_parts_mut(alloc_start, layout.size()))
     }
 
-    unsafe fn dealloc(&mut self, _ptr: *mut u8, _layout: Layout) {
+    unsafe fn dealloc(&mut self, _ptr: *mut u8, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
         self.allocations -= 1;
-        if self.allocations == 0 {
-            self.tip = self.region.as_mut_ptr();
+        if self.auto_reset {
+            self.frame_boundary();
+        }
+    }
+
+    unsafe fn grow_in_place(&mut self, ptr: *mut u8, layout: Layout, new_size: usize) -> bool {
+        // only the most recent allocation can grow
+        if ptr.addr() + layout.size() != self.tip.addr() {
+            return false;
+        }
+        match ptr.addr().checked_add(new_size) {
+            Some(end) if end <= self.region_range().end => {
+                self.tip = ptr.with_addr(end);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    unsafe fn shrink_in_place(&mut self, ptr: *mut u8, layout: Layout, new_size: usize) -> bool {
+        // only the most recent allocation can give back its tail, which
+        // the next allocation then reuses. It cannot shrink to nothing, as
+        // freeing it would then be taken for freeing a zero-sized allocation
+        if ptr.addr() + layout.size() != self.tip.addr()
+            || new_size > layout.size()
+            || new_size == 0
+        {
+            return false;
+        }
+        self.tip = ptr.with_addr(ptr.addr() + new_size);
+        true
+    }
+}
+
+/// A bump allocator that owns its storage inline, for a small scratch arena
+/// embedded in another value. Memory is allocated through the handle returned
+/// by [`allocator`](InlineBump::allocator).
+///
+/// It keeps the offset of the next allocation into its storage rather than a
+/// pointer, so the arena itself can be moved freely, but moving it moves the
+/// memory of any live allocation along with it. Allocations must therefore
+/// not be used once the arena has moved.
+pub struct InlineBump<const N: usize> {
+    storage: [u8; N],
+    offset: usize,
+    allocations: u64,
+}
+
+impl<const N: usize> InlineBump<N> {
+    /// Creates an arena with all `N` bytes of its storage free.
+    pub const fn new() -> Self {
+        Self {
+            storage: [0; N],
+            offset: 0,
+            allocations: 0,
+        }
+    }
+
+    /// Returns a handle that allocates from the storage. Allocations made
+    /// through it stay usable across calls on the handle, but not once the
+    /// arena is borrowed again, since that borrow covers the storage as well.
+    pub fn allocator(&mut self) -> InlineAllocator<'_, N> {
+        let arena = NonNull::from(self);
+        InlineAllocator {
+            arena,
+            // taken once, so that allocating does not reborrow the storage
+            // and invalidate the allocations already handed out
+            base: unsafe { ptr::addr_of_mut!((*arena.as_ptr()).storage) }.cast(),
+            _arena: PhantomData,
+        }
+    }
+
+    /// Returns the number of bytes between the next allocation and the end of
+    /// the storage.
+    pub fn remaining(&self) -> usize {
+        N - self.offset
+    }
+
+    /// Returns whether there are no outstanding allocations.
+    pub fn is_empty(&self) -> bool {
+        self.allocations == 0
+    }
+}
+
+impl<const N: usize> Default for InlineBump<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Allocates from the storage of an [`InlineBump`], see
+/// [`InlineBump::allocator`].
+pub struct InlineAllocator<'a, const N: usize> {
+    arena: NonNull<InlineBump<N>>,
+    base: *mut u8,
+    _arena: PhantomData<&'a mut InlineBump<N>>,
+}
+
+unsafe impl<const N: usize> super::Allocator for InlineAllocator<'_, N> {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<NonNull<[u8]>> {
+        if layout.size() == 0 {
+            return Some(crate::dangling(layout));
+        }
+        let arena = self.arena.as_ptr();
+        // the storage is only as aligned as a byte array, so the alignment
+        // has to be worked out from where it is now
+        let alloc_start = self
+            .base
+            .wrapping_add(unsafe { (*arena).offset })
+            .try_align_up(layout.align())?;
+        let start = alloc_start.addr() - self.base.addr();
+        let end = start.checked_add(layout.size())?;
+        if end > N {
+            return None;
+        }
+        unsafe {
+            (*arena).allocations = (*arena).allocations.checked_add(1)?;
+            (*arena).offset = end;
+        }
+        NonNull::new(slice_from_raw_parts_mut(alloc_start, layout.size()))
+    }
+
+    unsafe fn dealloc(&mut self, _ptr: *mut u8, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
+        let arena = self.arena.as_ptr();
+        unsafe {
+            (*arena).allocations -= 1;
+            if (*arena).allocations == 0 {
+                (*arena).offset = 0;
+            }
         }
     }
 }
@@ -46,10 +406,14 @@ mod tests {
     use core::{
         alloc::Layout,
         cell::SyncUnsafeCell,
+        marker::PhantomData,
+        mem,
         ptr::{addr_of_mut, slice_from_raw_parts_mut, NonNull},
     };
 
-    use super::Allocator;
+    use static_assertions::const_assert_eq;
+
+    use super::{min_bump_region_size, split_into, Allocator, Builder, InlineBump};
     use crate::Allocator as _;
 
     #[repr(align(8))]
@@ -79,4 +443,317 @@ mod tests {
             alloc.alloc(l3).unwrap();
         }
     }
+
+    #[test]
+    fn empty_and_full() {
+        let mut heap = [0u64; 2];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let mut alloc = Allocator::from_slice(heap);
+        let l = Layout::new::<u64>();
+        assert!(alloc.is_empty() && !alloc.is_full());
+        unsafe {
+            let p1 = alloc.alloc(l).unwrap();
+            assert!(!alloc.is_empty() && !alloc.is_full());
+            let p2 = alloc.alloc(l).unwrap();
+            assert!(alloc.is_full());
+            alloc.dealloc(p1.as_mut_ptr(), l);
+            // the tip only rewinds once everything has been freed
+            assert!(!alloc.is_empty() && alloc.is_full());
+            alloc.dealloc(p2.as_mut_ptr(), l);
+            assert!(alloc.is_empty() && !alloc.is_full());
+        }
+    }
+
+    const_assert_eq!(min_bump_region_size(Layout::new::<u64>()), 15);
+
+    // nothing but the fields, padded at the end to the alignment: on 64-bit
+    // targets the fields take up 16 + 8 + 8 + 3 = 35 bytes, rounded up to 40
+    const_assert_eq!(
+        mem::size_of::<Allocator>(),
+        (mem::size_of::<NonNull<[u8]>>()
+            + mem::size_of::<*mut u8>()
+            + mem::size_of::<u64>()
+            + 3 * mem::size_of::<bool>()
+            + mem::size_of::<PhantomData<&mut [u8]>>())
+        .next_multiple_of(mem::align_of::<Allocator>())
+    );
+
+    #[test]
+    fn min_region_size() {
+        const L: Layout = Layout::new::<u64>();
+        const SIZE: usize = min_bump_region_size(L);
+        static HEAP: SyncUnsafeCell<MemPool<{ SIZE + 8 }>> =
+            SyncUnsafeCell::new(MemPool([0; SIZE + 8]));
+        for offset in 0..L.align() {
+            let mut alloc = Allocator::new(
+                NonNull::new(slice_from_raw_parts_mut(
+                    unsafe { addr_of_mut!((*HEAP.get()).0) }
+                        .cast::<u8>()
+                        .wrapping_add(offset),
+                    SIZE,
+                ))
+                .unwrap(),
+            );
+            assert!(unsafe { alloc.alloc(L) }.is_some());
+        }
+    }
+
+    #[test]
+    fn split() {
+        const HEAP_SIZE: usize = 1 << 6;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let region = NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(1), 62)).unwrap();
+
+        let allocs = split_into::<4>(region);
+        for (i, alloc) in allocs.iter().enumerate() {
+            let range = alloc.region_range();
+            assert_eq!(range.start, heap.addr() + 8 + 8 * i);
+            assert_eq!(range.len(), 8);
+        }
+        assert!(allocs
+            .iter()
+            .zip(&allocs[1..])
+            .all(|(a, b)| a.region_range().end <= b.region_range().start));
+
+        assert!(split_into::<0>(region).is_empty());
+        let mut tiny = split_into::<16>(region);
+        assert!(tiny.iter().all(|alloc| alloc.region_range().is_empty()));
+        assert!(unsafe { tiny[0].alloc(Layout::new::<u8>()) }.is_none());
+    }
+
+    #[test]
+    fn frame_boundary() {
+        const HEAP_SIZE: usize = 1 << 5;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc =
+            Allocator::per_frame(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+        let l = Layout::new::<u64>();
+        unsafe {
+            let p1 = alloc.alloc(l).unwrap();
+            let p2 = alloc.alloc(l).unwrap();
+            alloc.dealloc(p1.as_mut_ptr(), l);
+            alloc.dealloc(p2.as_mut_ptr(), l);
+            // freeing everything mid-frame does not reuse memory
+            assert_eq!(alloc.tip, heap.wrapping_add(16));
+            let p3 = alloc.alloc(l).unwrap();
+            assert_eq!(p3.as_mut_ptr(), heap.wrapping_add(16));
+
+            // nor does a boundary while something is still live
+            alloc.frame_boundary();
+            assert_eq!(alloc.tip, heap.wrapping_add(24));
+
+            alloc.dealloc(p3.as_mut_ptr(), l);
+            alloc.frame_boundary();
+            assert_eq!(alloc.tip, heap);
+        }
+
+        let alloc = Builder::new(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap())
+            .auto_reset(false)
+            .build();
+        assert!(!alloc.auto_reset());
+    }
+
+    #[test]
+    fn rebase() {
+        const HEAP_SIZE: usize = 1 << 5;
+        static HEAP1: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        static HEAP2: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap1 = unsafe { addr_of_mut!((*HEAP1.get()).0) }.cast::<u8>();
+        let heap2 = unsafe { addr_of_mut!((*HEAP2.get()).0) }.cast::<u8>();
+        let mut alloc =
+            Allocator::new(NonNull::new(slice_from_raw_parts_mut(heap1, HEAP_SIZE)).unwrap());
+        let l = Layout::new::<u64>();
+        unsafe {
+            alloc.alloc(l).unwrap();
+            alloc.alloc(l).unwrap();
+            alloc.rebase(NonNull::new(slice_from_raw_parts_mut(heap2, HEAP_SIZE)).unwrap());
+        }
+        assert_eq!(alloc.region_range().start, heap2.addr());
+        assert_eq!(alloc.tip.addr() - heap2.addr(), 16);
+        unsafe {
+            assert_eq!(alloc.alloc(l).unwrap().as_mut_ptr(), heap2.wrapping_add(16));
+            assert_eq!(alloc.alloc(l).unwrap().as_mut_ptr(), heap2.wrapping_add(24));
+            assert!(alloc.alloc(l).is_none());
+        }
+    }
+
+    #[test]
+    fn as_bytes() {
+        let mut heap = [0u64; 4];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let mut alloc: Allocator = heap.into();
+        let l = Layout::new::<u64>();
+        unsafe {
+            alloc.alloc(l).unwrap();
+            let p = alloc.alloc(l).unwrap().as_mut_ptr().cast::<u64>();
+            p.write(u64::from_ne_bytes(*b"sentinel"));
+        }
+        assert_eq!(&alloc.as_bytes().unwrap()[8..16], b"sentinel");
+        alloc.as_bytes_mut().unwrap()[..8].copy_from_slice(b"scribble");
+        assert_eq!(&alloc.as_bytes().unwrap()[..8], b"scribble");
+    }
+
+    #[test]
+    fn as_bytes_unowned() {
+        let mut heap = [0u8; 16];
+        let mut alloc = Allocator::new(NonNull::from(&mut heap[..]));
+        assert!(alloc.as_bytes().is_none());
+        assert!(alloc.as_bytes_mut().is_none());
+    }
+
+    #[test]
+    fn shrink_in_place() {
+        let mut heap = [0u64; 4];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let start = heap.as_mut_ptr();
+        let mut alloc = Allocator::from_slice(heap);
+        let l = Layout::new::<u64>();
+        unsafe {
+            let a = alloc.alloc(l).unwrap().as_mut_ptr();
+            let b = alloc.alloc(Layout::new::<[u64; 3]>()).unwrap().as_mut_ptr();
+            assert!(!alloc.shrink_in_place(a, l, 4));
+            assert!(alloc.shrink_in_place(b, Layout::new::<[u64; 3]>(), 12));
+            // the next allocation is aligned up past the kept part of b
+            assert_eq!(alloc.alloc(l).unwrap().as_mut_ptr(), start.wrapping_add(24));
+            assert!(alloc.alloc(l).is_none());
+        }
+    }
+
+    #[test]
+    fn realloc_to_zero() {
+        let mut heap = [0u64; 2];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let mut alloc = Allocator::from_slice(heap);
+        let l = Layout::new::<u64>();
+        unsafe {
+            let p = alloc.alloc(l).unwrap().as_mut_ptr();
+            assert!(!alloc.shrink_in_place(p, l, 0));
+            let z = alloc.realloc(p, l, 0).unwrap();
+            assert_eq!(z.len(), 0);
+            // the original allocation was freed, so the allocator rewound
+            assert!(alloc.is_empty());
+            assert_eq!(alloc.remaining(), 16);
+            alloc.dealloc(z.as_mut_ptr(), Layout::from_size_align(0, 8).unwrap());
+        }
+        assert!(alloc.is_empty());
+    }
+
+    #[test]
+    fn zero_size() {
+        let mut heap = [0u64; 2];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let mut alloc = Allocator::from_slice(heap);
+        let l = Layout::from_size_align(0, 4096).unwrap();
+        unsafe {
+            let p = alloc.alloc(l).unwrap();
+            assert_eq!((p.as_mut_ptr().addr(), p.len()), (4096, 0));
+            assert!(alloc.is_empty());
+            assert_eq!(alloc.remaining(), 16);
+            alloc.dealloc(p.as_mut_ptr(), l);
+        }
+        assert_eq!(alloc.remaining(), 16);
+    }
+
+    #[test]
+    fn reset_to() {
+        let mut heap = [0u64; 8];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let start = heap.as_mut_ptr();
+        let mut alloc = Allocator::from_slice(heap);
+        let reserved = Layout::new::<[u64; 3]>();
+        let scratch = Layout::new::<[u64; 2]>();
+        unsafe {
+            let r = alloc.alloc(reserved).unwrap().as_mut_ptr();
+            r.write_bytes(0xaa, reserved.size());
+            for _ in 0..3 {
+                let a = alloc.alloc(scratch).unwrap().as_mut_ptr();
+                let b = alloc.alloc(scratch).unwrap().as_mut_ptr();
+                assert_eq!([a, b], [24, 40].map(|i| start.wrapping_add(i)));
+                a.write_bytes(0x55, 2 * scratch.size());
+                alloc.dealloc(a, scratch);
+                alloc.dealloc(b, scratch);
+                // the reserved block keeps the allocator from rewinding
+                assert_eq!(alloc.remaining(), 8);
+                alloc.reset_to(reserved.size());
+            }
+            assert_eq!(alloc.remaining(), 40);
+            assert!(!alloc.is_empty());
+            assert!(alloc.as_bytes().unwrap()[..reserved.size()]
+                .iter()
+                .all(|&b| b == 0xaa));
+        }
+    }
+
+    #[test]
+    fn scrub_on_drop() {
+        let mut heap = [0u64; 4];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let mut alloc = Builder::from_slice(heap).scrub_on_drop(true).build();
+        assert!(alloc.scrub_on_drop());
+        let l = Layout::new::<[u8; 12]>();
+        unsafe {
+            let p = alloc.alloc(l).unwrap();
+            p.as_mut_ptr().write_bytes(0x5e, 12);
+            // even memory that is still allocated is scrubbed
+            assert!(alloc.as_bytes().unwrap()[..12].iter().all(|&b| b == 0x5e));
+        }
+        drop(alloc);
+        assert!(heap.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn scrub_on_drop_unowned() {
+        let mut heap = [0u64; 4];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let mut alloc = Builder::new(NonNull::from(&mut *heap))
+            .scrub_on_drop(true)
+            .build();
+        // nothing guarantees that the region is not used elsewhere
+        assert!(!alloc.scrub_on_drop());
+        let l = Layout::new::<[u8; 12]>();
+        unsafe {
+            let p = alloc.alloc(l).unwrap();
+            p.as_mut_ptr().write_bytes(0x5e, 12);
+        }
+        drop(alloc);
+        assert!(heap[..12].iter().all(|&b| b == 0x5e));
+    }
+
+    #[test]
+    fn inline_bump() {
+        let mut arena = InlineBump::<128>::new();
+        let l = Layout::new::<u64>();
+        let mut alloc = arena.allocator();
+        unsafe {
+            let p = alloc.alloc(l).unwrap().as_mut_ptr().cast::<u64>();
+            assert!(p.is_aligned());
+            p.write(7);
+            let q = alloc.alloc(l).unwrap().as_mut_ptr().cast::<u64>();
+            q.write(8);
+            assert_eq!((p.read(), q.read()), (7, 8));
+            assert!(alloc.alloc(Layout::new::<[u8; 128]>()).is_none());
+            alloc.dealloc(p.cast(), l);
+            alloc.dealloc(q.cast(), l);
+        }
+        assert_eq!(arena.remaining(), 128);
+
+        // with nothing live, the arena can be moved and used where it ends up
+        let mut moved = [arena];
+        let arena = &mut moved[0];
+        let storage = arena.storage.as_ptr_range();
+        let mut alloc = arena.allocator();
+        unsafe {
+            let p = alloc.alloc(l).unwrap();
+            assert!(storage.contains(&p.as_mut_ptr().cast_const()));
+            p.as_mut_ptr().cast::<u64>().write(9);
+            alloc.dealloc(p.as_mut_ptr(), l);
+        }
+        assert!(arena.is_empty());
+    }
 }
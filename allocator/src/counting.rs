@@ -0,0 +1,169 @@
+use core::{alloc::Layout, ptr::NonNull};
+
+use crate::AllocError;
+
+// Wraps another allocator and counts the calls made to it and the bytes it
+// hands out, without changing its behaviour.
+
+pub struct Allocator<A>(A, Counters);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counters {
+    alloc_calls: u64,
+    dealloc_calls: u64,
+    bytes_allocated: usize,
+    bytes_freed: usize,
+    peak_live_bytes: usize,
+}
+
+impl Counters {
+    /// Number of successful allocations.
+    pub fn alloc_calls(&self) -> u64 {
+        self.alloc_calls
+    }
+
+    /// Number of deallocations.
+    pub fn dealloc_calls(&self) -> u64 {
+        self.dealloc_calls
+    }
+
+    /// Total bytes requested by successful allocations.
+    pub fn bytes_allocated(&self) -> usize {
+        self.bytes_allocated
+    }
+
+    /// Total bytes released by deallocations.
+    pub fn bytes_freed(&self) -> usize {
+        self.bytes_freed
+    }
+
+    /// Bytes currently allocated.
+    pub fn live_bytes(&self) -> usize {
+        self.bytes_allocated - self.bytes_freed
+    }
+
+    /// Largest number of bytes that were allocated at once.
+    pub fn peak_live_bytes(&self) -> usize {
+        self.peak_live_bytes
+    }
+
+    fn allocated(&mut self, size: usize) {
+        self.bytes_allocated += size;
+        self.peak_live_bytes = Ord::max(self.peak_live_bytes, self.live_bytes());
+    }
+}
+
+impl<A> Allocator<A> {
+    pub const fn new(inner: A) -> Self {
+        Self(
+            inner,
+            Counters {
+                alloc_calls: 0,
+                dealloc_calls: 0,
+                bytes_allocated: 0,
+                bytes_freed: 0,
+                peak_live_bytes: 0,
+            },
+        )
+    }
+
+    pub fn counters(&self) -> &Counters {
+        &self.1
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> A {
+        self.0
+    }
+}
+
+unsafe impl<A: super::Allocator> super::Allocator for Allocator<A> {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<NonNull<[u8]>> {
+        unsafe { self.try_alloc(layout) }.ok()
+    }
+
+    unsafe fn try_alloc(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = unsafe { self.0.try_alloc(layout) }?;
+        self.1.alloc_calls += 1;
+        self.1.allocated(layout.size());
+        Ok(ptr)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.0.dealloc(ptr, layout) };
+        self.1.dealloc_calls += 1;
+        self.1.bytes_freed += layout.size();
+    }
+
+    unsafe fn dealloc_unchecked(&mut self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.0.dealloc_unchecked(ptr, layout) };
+        self.1.dealloc_calls += 1;
+        self.1.bytes_freed += layout.size();
+    }
+
+    unsafe fn grow_in_place(&mut self, ptr: *mut u8, layout: Layout, new_size: usize) -> bool {
+        let grown = unsafe { self.0.grow_in_place(ptr, layout, new_size) };
+        if grown {
+            self.1.allocated(new_size - layout.size());
+        }
+        grown
+    }
+
+    unsafe fn shrink_in_place(&mut self, ptr: *mut u8, layout: Layout, new_size: usize) -> bool {
+        let shrunk = unsafe { self.0.shrink_in_place(ptr, layout, new_size) };
+        if shrunk {
+            self.1.bytes_freed += layout.size() - new_size;
+        }
+        shrunk
+    }
+}
+
+#[cfg(all(test, feature = "bump"))]
+mod tests {
+    use core::{
+        alloc::Layout,
+        cell::SyncUnsafeCell,
+        ptr::{addr_of_mut, slice_from_raw_parts_mut, NonNull},
+    };
+
+    use super::Allocator;
+    use crate::{bump, Allocator as _};
+
+    #[repr(align(8))]
+    struct MemPool<const N: usize>([u8; N]);
+
+    #[test]
+    fn test() {
+        const HEAP_SIZE: usize = 1 << 5;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let mut alloc = Allocator::new(bump::Allocator::new(
+            NonNull::new(slice_from_raw_parts_mut(
+                unsafe { addr_of_mut!((*HEAP.get()).0) }.cast(),
+                HEAP_SIZE,
+            ))
+            .unwrap(),
+        ));
+        let l1 = Layout::new::<u64>();
+        let l2 = Layout::new::<[u64; 2]>();
+        unsafe {
+            let p1 = alloc.alloc(l1).unwrap();
+            let p2 = alloc.alloc(l2).unwrap();
+            assert!(alloc.alloc(l2).is_none());
+            alloc.dealloc(p1.as_mut_ptr(), l1);
+            let p3 = alloc.alloc(l1).unwrap();
+            alloc.dealloc(p2.as_mut_ptr(), l2);
+            alloc.dealloc(p3.as_mut_ptr(), l1);
+        }
+        let counters = alloc.counters();
+        assert_eq!(counters.alloc_calls(), 3);
+        assert_eq!(counters.dealloc_calls(), 3);
+        assert_eq!(counters.bytes_allocated(), 32);
+        assert_eq!(counters.bytes_freed(), 32);
+        assert_eq!(counters.live_bytes(), 0);
+        assert_eq!(counters.peak_live_bytes(), 24);
+    }
+}
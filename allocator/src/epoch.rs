@@ -0,0 +1,225 @@
+use core::{
+    alloc::Layout,
+    marker::PhantomData,
+    ops::Range,
+    ptr::{slice_from_raw_parts_mut, NonNull},
+};
+
+use ptr_ext::PtrExt;
+
+// Bump allocator whose allocations are grouped into nested epochs. Each epoch
+// counts its live allocations, so that once every allocation in the topmost
+// epochs has been freed, in whatever order, the tip rewinds to where those
+// epochs began. Memory is reclaimed for workloads that free in roughly LIFO
+// order without requiring it within an epoch.
+
+/// The most epochs that can be open at once, including the base epoch.
+pub const MAX_EPOCHS: usize = 8;
+
+/// An epoch bump allocator with exclusive use of its region for `'a`.
+pub struct Allocator<'a> {
+    region: NonNull<[u8]>,
+    tip: *mut u8,
+    // (start address, live allocations) of each open epoch, oldest first.
+    // An allocation belongs to the epoch it lies in, as epochs never overlap
+    epochs: [(usize, u64); MAX_EPOCHS],
+    depth: usize,
+    _region: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> Allocator<'a> {
+    /// Creates an allocator handing out memory from `region`, in the base
+    /// epoch.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that
+    /// `region` is valid and unused by anything else for `'a`.
+    pub unsafe fn new(region: NonNull<[u8]>) -> Allocator<'a> {
+        let mut epochs = [(0, 0); MAX_EPOCHS];
+        epochs[0].0 = region.addr().get();
+        Allocator {
+            region,
+            tip: region.as_mut_ptr(),
+            epochs,
+            depth: 1,
+            _region: PhantomData,
+        }
+    }
+
+    /// Creates an allocator handing out memory from `slice`, which it
+    /// borrows for as long as it lives.
+    pub fn from_slice(slice: &'a mut [u8]) -> Allocator<'a> {
+        unsafe { Allocator::new(NonNull::from(slice)) }
+    }
+
+    /// Starts a new epoch on top of the current one, which later allocations
+    /// belong to. Returns `false` if [`MAX_EPOCHS`] are already open.
+    pub fn begin_epoch(&mut self) -> bool {
+        if self.depth == MAX_EPOCHS {
+            return false;
+        }
+        self.epochs[self.depth] = (self.tip.addr(), 0);
+        self.depth += 1;
+        true
+    }
+
+    /// Ends the current epoch. Its live allocations are handed to the epoch
+    /// below, which becomes current again. The base epoch cannot be ended.
+    pub fn end_epoch(&mut self) {
+        assert!(self.depth > 1, "cannot end the base epoch");
+        self.depth -= 1;
+        self.epochs[self.depth - 1].1 += self.epochs[self.depth].1;
+    }
+
+    /// Returns the index of the current epoch, 0 being the base epoch.
+    pub fn current_epoch(&self) -> usize {
+        self.depth - 1
+    }
+
+    /// Returns the number of live allocations in the given open epoch.
+    pub fn live(&self, epoch: usize) -> Option<u64> {
+        self.epochs[..self.depth].get(epoch).map(|&(_, live)| live)
+    }
+
+    /// Returns the range of addresses managed by this allocator.
+    pub fn region_range(&self) -> Range<usize> {
+        let start = self.region.addr().get();
+        start..start + self.region.len()
+    }
+
+    /// Rewinds the tip to the start of the lowest epoch above which no epoch
+    /// has live allocations. The empty epochs that stay open start over at
+    /// the new tip, so that later allocations do not lie in an older epoch.
+    fn rewind(&mut self) {
+        let first_empty = self.epochs[..self.depth]
+            .iter()
+            .rposition(|&(_, live)| live > 0)
+            .map_or(0, |i| i + 1);
+        if first_empty < self.depth {
+            let start = self.epochs[first_empty].0;
+            self.tip = self.region.as_mut_ptr().with_addr(start);
+            for epoch in &mut self.epochs[first_empty..self.depth] {
+                epoch.0 = start;
+            }
+        }
+    }
+}
+
+unsafe impl super::Allocator for Allocator<'_> {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<NonNull<[u8]>> {
+        // a zero-sized allocation does not lie in any epoch
+        if layout.size() == 0 {
+            return Some(crate::dangling(layout));
+        }
+        let alloc_start = self.tip.try_align_up(layout.align())?;
+        let alloc_end = alloc_start.with_addr(alloc_start.addr().checked_add(layout.size())?);
+        if alloc_end.addr() > self.region_range().end {
+            return None;
+        }
+        let live = &mut self.epochs[self.depth - 1].1;
+        *live = live.checked_add(1)?;
+        self.tip = alloc_end;
+        NonNull::new(slice_from_raw_parts_mut(alloc_start, layout.size()))
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
+        let epoch = self.epochs[..self.depth]
+            .iter()
+            .rposition(|&(start, _)| start <= ptr.addr())
+            .unwrap_or(0);
+        self.epochs[epoch].1 -= 1;
+        self.rewind();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use super::Allocator;
+    use crate::Allocator as _;
+
+    #[test]
+    fn test() {
+        let mut heap = [0u64; 8];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let start = heap.as_mut_ptr();
+        let mut alloc = Allocator::from_slice(heap);
+        let l = Layout::new::<u64>();
+        unsafe {
+            let a = alloc.alloc(l).unwrap();
+            let b = alloc.alloc(l).unwrap();
+            assert!(alloc.begin_epoch());
+            let c = alloc.alloc(l).unwrap();
+            let d = alloc.alloc(l).unwrap();
+            assert_eq!((alloc.live(0), alloc.live(1)), (Some(2), Some(2)));
+
+            // freeing part of the lower epoch reclaims nothing
+            alloc.dealloc(a.as_mut_ptr(), l);
+            assert_eq!(alloc.tip, start.wrapping_add(32));
+
+            // freeing all of the top epoch, out of order, rewinds past it
+            alloc.dealloc(c.as_mut_ptr(), l);
+            assert_eq!(alloc.tip, start.wrapping_add(32));
+            alloc.dealloc(d.as_mut_ptr(), l);
+            assert_eq!(alloc.tip, start.wrapping_add(16));
+            assert_eq!(alloc.alloc(l).unwrap(), c);
+
+            // once the lower epoch is empty as well, everything is reclaimed
+            alloc.end_epoch();
+            assert_eq!(alloc.live(0), Some(2));
+            alloc.dealloc(c.as_mut_ptr(), l);
+            alloc.dealloc(b.as_mut_ptr(), l);
+            assert_eq!(alloc.tip, start);
+        }
+    }
+
+    #[test]
+    fn rewind_open_epochs() {
+        let mut heap = [0u64; 8];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let start = heap.as_mut_ptr();
+        let mut alloc = Allocator::from_slice(heap);
+        let l = Layout::new::<u64>();
+        unsafe {
+            let _a = alloc.alloc(l).unwrap();
+            assert!(alloc.begin_epoch());
+            let b = alloc.alloc(l).unwrap();
+            assert!(alloc.begin_epoch());
+            let c = alloc.alloc(l).unwrap();
+            alloc.dealloc(b.as_mut_ptr(), l);
+            alloc.dealloc(c.as_mut_ptr(), l);
+            assert_eq!(alloc.tip, start.wrapping_add(8));
+
+            // lands where `b` was, but belongs to the current epoch
+            let d = alloc.alloc(l).unwrap();
+            assert_eq!(d, b);
+            assert_eq!((alloc.live(1), alloc.live(2)), (Some(0), Some(1)));
+            alloc.dealloc(d.as_mut_ptr(), l);
+            assert_eq!(alloc.live(2), Some(0));
+            assert_eq!(alloc.tip, start.wrapping_add(8));
+        }
+    }
+
+    #[test]
+    fn zero_size() {
+        let mut heap = [0u64; 2];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let start = heap.as_mut_ptr();
+        let mut alloc = Allocator::from_slice(heap);
+        let l = Layout::from_size_align(0, 8).unwrap();
+        unsafe {
+            let p = alloc.alloc(l).unwrap();
+            assert_eq!((p.as_mut_ptr().addr(), p.len()), (8, 0));
+            assert_eq!(alloc.live(0), Some(0));
+            assert!(alloc.begin_epoch());
+            alloc.dealloc(p.as_mut_ptr(), l);
+            assert_eq!((alloc.live(0), alloc.live(1)), (Some(0), Some(0)));
+            assert_eq!(alloc.tip, start);
+        }
+    }
+}
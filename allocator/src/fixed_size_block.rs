@@ -0,0 +1,320 @@
+use core::{
+    alloc::Layout,
+    mem,
+    ptr::{self, NonNull},
+};
+
+use super::linked_list::{self, RegionError};
+use crate::unwrap_invariant;
+
+// based off https://os.phil-opp.com/allocator-designs/#fixed-size-block-allocator
+
+/// The block sizes to use.
+///
+/// The sizes must each be power of 2 because they are also used as
+/// the block alignment (alignments must be always powers of 2).
+const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+/// The block size of the largest size class.
+const MAX_BLOCK_SIZE: usize = BLOCK_SIZES[BLOCK_SIZES.len() - 1];
+
+/// The block size of the smallest size class.
+const MIN_BLOCK_SIZE: usize = BLOCK_SIZES[0];
+
+pub struct Allocator {
+    list_heads: [Option<NonNull<ListNode>>; BLOCK_SIZES.len()],
+    fallback_allocator: linked_list::Allocator,
+    max_slab_size: usize,
+}
+
+impl Allocator {
+    /// Creates an empty Allocator.
+    pub const fn new() -> Self {
+        Self {
+            list_heads: [None; BLOCK_SIZES.len()],
+            fallback_allocator: linked_list::Allocator::new(),
+            max_slab_size: usize::MAX,
+        }
+    }
+
+    /// Creates an Allocator with `region` as its only memory region, or
+    /// returns why the region cannot be used. [`Allocator::new`] followed by
+    /// [`add_free_region`](Allocator::add_free_region) panics instead.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that the given
+    /// memory region is valid and unused.
+    pub unsafe fn try_new(region: NonNull<[u8]>) -> Result<Self, InitError> {
+        if !region
+            .as_mut_ptr()
+            .is_aligned_to(mem::align_of::<ListNode>())
+        {
+            return Err(InitError::Misaligned);
+        }
+        if region.len() < MIN_BLOCK_SIZE {
+            return Err(InitError::TooSmall);
+        }
+        let mut alloc = Self::new();
+        match unsafe { alloc.fallback_allocator.try_add_free_region(region) } {
+            Ok(_) => Ok(alloc),
+            // the fallback allocator has its own minimum, which may be larger
+            Err(RegionError::TooSmall) => Err(InitError::TooSmall),
+            Err(RegionError::Misaligned) => Err(InitError::Misaligned),
+            Err(RegionError::Overflow) => Err(InitError::Overflow),
+            // a new allocator has every region slot free
+            Err(RegionError::TooManyRegions) => unsafe { unwrap_invariant(None) },
+        }
+    }
+
+    /// Creates an empty Allocator that sends requests for more than
+    /// `max_slab_size` bytes to the fallback allocator, even if they fit a
+    /// size class. This stops large arrays from wasting most of a big block.
+    pub const fn with_max_slab_size(max_slab_size: usize) -> Self {
+        Self {
+            max_slab_size,
+            ..Self::new()
+        }
+    }
+
+    /// Returns the allocator that new blocks and requests too large for a
+    /// size class come from.
+    pub fn fallback(&self) -> &linked_list::Allocator {
+        &self.fallback_allocator
+    }
+
+    /// Returns the index of the size class `layout` is served from, or `None`
+    /// if it goes to the fallback allocator.
+    fn class_of(&self, layout: Layout) -> Option<usize> {
+        if layout.size() > self.max_slab_size {
+            return None;
+        }
+        size_class_of(layout)
+    }
+
+    /// Adds the given memory region to the fallback allocator.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that the given
+    /// memory region is valid and unused.
+    pub unsafe fn add_free_region(&mut self, region: NonNull<[u8]>) {
+        unsafe { self.fallback_allocator.add_free_region(region) };
+    }
+}
+
+impl Default for Allocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the block sizes of the size classes, in increasing order.
+pub fn size_classes() -> &'static [usize] {
+    BLOCK_SIZES
+}
+
+/// Returns the index into [`size_classes`] of the size class used for
+/// `layout`, or `None` if it is too large for any class and goes to the
+/// fallback allocator.
+pub fn size_class_of(layout: Layout) -> Option<usize> {
+    let required_block_size = Ord::max(layout.size(), layout.align());
+    // too large for any class, so there is nothing to search
+    if required_block_size > MAX_BLOCK_SIZE {
+        return None;
+    }
+    #[cfg(test)]
+    tests::CLASS_SEARCHES.with(|searches| searches.set(searches.get() + 1));
+    BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
+}
+
+unsafe impl super::Allocator for Allocator {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<NonNull<[u8]>> {
+        let Some(index) = self.class_of(layout) else {
+            return unsafe { self.fallback_allocator.alloc(layout) };
+        };
+        let block_size = BLOCK_SIZES[index];
+        match self.list_heads[index] {
+            Some(node) => {
+                self.list_heads[index] = unsafe { (*node.as_ptr()).next };
+                NonNull::new(ptr::slice_from_raw_parts_mut(
+                    node.as_ptr().cast(),
+                    block_size,
+                ))
+            }
+            None => {
+                // no block exists in list => allocate new block
+                // only works if all block sizes are a power of 2
+                let block_align = block_size;
+                let layout = Layout::from_size_align(block_size, block_align).unwrap();
+                unsafe { self.fallback_allocator.alloc(layout) }
+            }
+        }
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let Some(index) = self.class_of(layout) else {
+            return unsafe { self.fallback_allocator.dealloc(ptr, layout) };
+        };
+        let new_node = ListNode {
+            next: self.list_heads[index].take(),
+        };
+        // verify that block has size and alignment required for storing node
+        assert!(mem::size_of::<ListNode>() <= BLOCK_SIZES[index]);
+        assert!(mem::align_of::<ListNode>() <= BLOCK_SIZES[index]);
+        let new_node_ptr = ptr.cast::<ListNode>();
+        unsafe {
+            new_node_ptr.write(new_node);
+        }
+        self.list_heads[index] = NonNull::new(new_node_ptr);
+    }
+}
+
+struct ListNode {
+    next: Option<NonNull<ListNode>>,
+}
+
+/// Reasons [`Allocator::try_new`] cannot set up an allocator over a region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InitError {
+    /// The region is shorter than the smallest block, or than a node of the
+    /// fallback allocator.
+    TooSmall,
+    /// The region does not start at an address aligned for a free list node.
+    Misaligned,
+    /// The region runs past the end of the address space.
+    Overflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        alloc::Layout,
+        cell::{Cell, SyncUnsafeCell},
+        ptr::{addr_of_mut, slice_from_raw_parts_mut, NonNull},
+    };
+
+    use super::{size_class_of, size_classes, Allocator, InitError, MIN_BLOCK_SIZE};
+    use crate::Allocator as _;
+
+    #[repr(align(8))]
+    struct MemPool<const N: usize>([u8; N]);
+
+    std::thread_local! {
+        /// How many times this thread searched the size classes.
+        pub(super) static CLASS_SEARCHES: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[test]
+    fn test() {
+        let class = |size, align| size_class_of(Layout::from_size_align(size, align).unwrap());
+        assert_eq!(class(1, 1), Some(0));
+        assert_eq!(class(8, 8), Some(0));
+        assert_eq!(class(9, 1), Some(1));
+        assert_eq!(class(16, 32), Some(2));
+        assert_eq!(class(2048, 8), Some(size_classes().len() - 1));
+        assert_eq!(class(2049, 8), None);
+        assert_eq!(class(8, 4096), None);
+
+        const HEAP_SIZE: usize = 1 << 12;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u8; 24]>();
+        unsafe {
+            alloc.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+            let p = alloc.alloc(l).unwrap();
+            assert_eq!(p.len(), 32);
+            alloc.dealloc(p.as_mut_ptr(), l);
+            assert_eq!(alloc.alloc(l), Some(p));
+        }
+    }
+
+    #[test]
+    fn max_slab_size() {
+        const HEAP_SIZE: usize = 1 << 12;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let mut alloc = Allocator::with_max_slab_size(64);
+        let (under, over) = (Layout::new::<[u8; 64]>(), Layout::new::<[u8; 65]>());
+        unsafe {
+            alloc.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+            // a slab block stays out of the fallback allocator once freed
+            let p = alloc.alloc(under).unwrap();
+            alloc.dealloc(p.as_mut_ptr(), under);
+            assert_eq!(alloc.fallback().used_bytes(), 64);
+
+            // whereas a request over the threshold goes back to it
+            let p = alloc.alloc(over).unwrap();
+            assert_eq!(alloc.fallback().used_bytes(), 64 + 72);
+            alloc.dealloc(p.as_mut_ptr(), over);
+            assert_eq!(alloc.fallback().used_bytes(), 64);
+        }
+    }
+
+    #[test]
+    fn larger_than_every_class() {
+        const HEAP_SIZE: usize = 1 << 12;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 375]>();
+        unsafe {
+            alloc.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+            let searches = CLASS_SEARCHES.with(Cell::get);
+            let p = alloc.alloc(l).unwrap();
+            alloc.dealloc(p.as_mut_ptr(), l);
+            assert_eq!(CLASS_SEARCHES.with(Cell::get), searches);
+            // served at its own size rather than rounded up to a class
+            assert_eq!(p.len(), 3000);
+
+            let p = alloc.alloc(Layout::new::<u64>()).unwrap();
+            alloc.dealloc(p.as_mut_ptr(), Layout::new::<u64>());
+            assert_eq!(CLASS_SEARCHES.with(Cell::get), searches + 2);
+        }
+    }
+
+    #[test]
+    fn try_new() {
+        const HEAP_SIZE: usize = 1 << 12;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0).cast::<u8>() };
+        let region =
+            |start: *mut u8, len| NonNull::new(slice_from_raw_parts_mut(start, len)).unwrap();
+        unsafe {
+            assert_eq!(
+                Allocator::try_new(region(heap, MIN_BLOCK_SIZE - 1)).err(),
+                Some(InitError::TooSmall)
+            );
+            assert_eq!(
+                Allocator::try_new(region(heap.wrapping_add(1), HEAP_SIZE - 1)).err(),
+                Some(InitError::Misaligned)
+            );
+
+            let mut alloc = Allocator::try_new(region(heap, HEAP_SIZE)).unwrap();
+            let l = Layout::new::<u64>();
+            let p = alloc.alloc(l).unwrap();
+            assert_eq!(p.as_mut_ptr(), heap);
+            alloc.dealloc(p.as_mut_ptr(), l);
+        }
+    }
+}
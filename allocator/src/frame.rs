@@ -0,0 +1,203 @@
+use core::{
+    alloc::Layout,
+    marker::PhantomData,
+    mem,
+    ptr::{slice_from_raw_parts_mut, NonNull},
+};
+
+use ptr_ext::PtrExt;
+
+use crate::{alloc_assert, PAGE_SIZE};
+
+// Hands out whole frames of FRAME_SIZE bytes, aligned to FRAME_SIZE, from a
+// region. Frames that have never been allocated are taken in address order,
+// and freed frames are kept on an intrusive free list stored in the frames
+// themselves.
+
+/// A frame allocator with exclusive use of its region for `'a`.
+pub struct Allocator<'a, const FRAME_SIZE: usize = PAGE_SIZE> {
+    // the whole frames in the region
+    frames: NonNull<[u8]>,
+    // frames from this index on have never been allocated
+    unused: usize,
+    free: Option<NonNull<FreeFrame>>,
+    free_count: usize,
+    _region: PhantomData<&'a mut [u8]>,
+}
+
+struct FreeFrame {
+    next: Option<NonNull<FreeFrame>>,
+}
+
+impl<'a, const FRAME_SIZE: usize> Allocator<'a, FRAME_SIZE> {
+    /// Creates an allocator handing out the frames that lie wholly within
+    /// `region`. `FRAME_SIZE` must be a power of two.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that
+    /// `region` is valid and unused by anything else for `'a`.
+    pub unsafe fn new(region: NonNull<[u8]>) -> Allocator<'a, FRAME_SIZE> {
+        assert!(
+            FRAME_SIZE.is_power_of_two() && FRAME_SIZE >= mem::size_of::<FreeFrame>(),
+            "invalid frame size"
+        );
+        let end = region.addr().get() + region.len();
+        let (first, count) = match region.as_mut_ptr().try_align_up(FRAME_SIZE) {
+            Some(first) if first.addr() <= end => (first, (end - first.addr()) / FRAME_SIZE),
+            _ => (region.as_mut_ptr(), 0),
+        };
+        let frames = NonNull::new(slice_from_raw_parts_mut(first, count * FRAME_SIZE)).unwrap();
+        Allocator {
+            frames,
+            unused: 0,
+            free: None,
+            free_count: 0,
+            _region: PhantomData,
+        }
+    }
+
+    /// Creates an allocator handing out the frames that lie wholly within
+    /// `slice`, which it borrows for as long as it lives.
+    pub fn from_slice(slice: &'a mut [u8]) -> Allocator<'a, FRAME_SIZE> {
+        unsafe { Allocator::new(NonNull::from(slice)) }
+    }
+
+    /// Returns the number of frames managed by the allocator.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len() / FRAME_SIZE
+    }
+
+    /// Returns the number of frames that are not allocated.
+    pub fn free_frame_count(&self) -> usize {
+        self.frame_count() - self.unused + self.free_count
+    }
+
+    /// Returns the index of the frame starting at `frame`, if it is one of
+    /// the allocator's frames.
+    pub fn frame_index(&self, frame: *const u8) -> Option<usize> {
+        let offset = frame.addr().checked_sub(self.frames.addr().get())?;
+        (offset % FRAME_SIZE == 0 && offset < self.frames.len()).then_some(offset / FRAME_SIZE)
+    }
+
+    /// Allocates a frame, reusing freed frames first.
+    pub fn alloc_frame(&mut self) -> Option<NonNull<u8>> {
+        if let Some(frame) = self.free {
+            self.free = unsafe { (*frame.as_ptr()).next };
+            self.free_count -= 1;
+            return Some(frame.cast());
+        }
+        if self.unused == self.frame_count() {
+            return None;
+        }
+        let frame = self
+            .frames
+            .as_mut_ptr()
+            .wrapping_add(self.unused * FRAME_SIZE);
+        self.unused += 1;
+        NonNull::new(frame)
+    }
+
+    /// Frees a frame returned by [`Allocator::alloc_frame`].
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that `frame`
+    /// is allocated and no longer used.
+    pub unsafe fn dealloc_frame(&mut self, frame: NonNull<u8>) {
+        alloc_assert(self.frame_index(frame.as_ptr()).is_some(), "invalid frame");
+        let node = frame.cast::<FreeFrame>();
+        unsafe { node.as_ptr().write(FreeFrame { next: self.free }) };
+        self.free = Some(node);
+        self.free_count += 1;
+    }
+}
+
+unsafe impl<const FRAME_SIZE: usize> super::Allocator for Allocator<'_, FRAME_SIZE> {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<NonNull<[u8]>> {
+        if layout.size() > FRAME_SIZE || layout.align() > FRAME_SIZE {
+            return None;
+        }
+        let frame = self.alloc_frame()?;
+        NonNull::new(slice_from_raw_parts_mut(frame.as_ptr(), FRAME_SIZE))
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, _layout: Layout) {
+        unsafe { self.dealloc_frame(NonNull::new_unchecked(ptr)) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        alloc::Layout,
+        cell::SyncUnsafeCell,
+        ptr::{addr_of_mut, slice_from_raw_parts_mut, NonNull},
+    };
+
+    use super::Allocator;
+    use crate::{Allocator as _, PAGE_SIZE};
+
+    #[repr(align(16384))]
+    struct MemPool<const N: usize>([u8; N]);
+
+    #[test]
+    fn test() {
+        let mut heap = [0u8; 4 * 64];
+        let mut alloc = Allocator::<64>::from_slice(&mut heap);
+        let count = alloc.frame_count();
+        assert!(count >= 3);
+        let a = alloc.alloc_frame().unwrap();
+        let b = alloc.alloc_frame().unwrap();
+        assert_eq!(a.as_ptr().wrapping_add(64), b.as_ptr());
+        assert_eq!(alloc.free_frame_count(), count - 2);
+        unsafe {
+            alloc.dealloc_frame(a);
+            assert_eq!(alloc.alloc_frame(), Some(a));
+            assert!(alloc
+                .alloc(Layout::from_size_align(128, 8).unwrap())
+                .is_none());
+            let c = alloc.alloc(Layout::new::<u64>()).unwrap();
+            assert_eq!((c.as_mut_ptr(), c.len()), (b.as_ptr().wrapping_add(64), 64));
+        }
+    }
+
+    #[test]
+    fn frame_sizes() {
+        const HEAP_SIZE: usize = 1 << 16;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        // the region starts one page into the pool
+        let region = NonNull::new(slice_from_raw_parts_mut(
+            heap.wrapping_add(PAGE_SIZE),
+            HEAP_SIZE - PAGE_SIZE,
+        ))
+        .unwrap();
+
+        // the region is handed to one allocator at a time
+        {
+            let mut small = unsafe { Allocator::<PAGE_SIZE>::new(region) };
+            assert_eq!(small.frame_count(), 15);
+            assert_eq!(
+                small.alloc_frame().unwrap().as_ptr(),
+                heap.wrapping_add(PAGE_SIZE)
+            );
+            let second = small.alloc_frame().unwrap();
+            assert_eq!(second.as_ptr(), heap.wrapping_add(2 * PAGE_SIZE));
+            assert_eq!(small.frame_index(second.as_ptr()), Some(1));
+            assert_eq!(small.free_frame_count(), 13);
+        }
+
+        // larger frames have to be aligned, so the first one starts further in
+        let mut large = unsafe { Allocator::<{ 4 * PAGE_SIZE }>::new(region) };
+        assert_eq!(large.frame_count(), 3);
+        let frames: [_; 3] = core::array::from_fn(|_| large.alloc_frame().unwrap().as_ptr());
+        assert_eq!(
+            frames,
+            [1, 2, 3].map(|i| heap.wrapping_add(i * 4 * PAGE_SIZE))
+        );
+        assert_eq!(large.alloc_frame(), None);
+        assert_eq!(large.frame_index(heap.wrapping_add(PAGE_SIZE)), None);
+    }
+}
@@ -0,0 +1,121 @@
+use core::{alloc::Layout, mem, ptr::NonNull};
+
+use crate::Allocator;
+
+/// An allocation that remembers its own layout, returned by
+/// [`Allocator::alloc_growable`].
+///
+/// The allocation is freed when the handle is dropped.
+pub struct GrowableAlloc<'a, A: Allocator> {
+    alloc: &'a mut A,
+    ptr: NonNull<[u8]>,
+    layout: Layout,
+}
+
+impl<'a, A: Allocator> GrowableAlloc<'a, A> {
+    pub(crate) fn new(alloc: &'a mut A, ptr: NonNull<[u8]>, layout: Layout) -> Self {
+        Self { alloc, ptr, layout }
+    }
+
+    pub fn as_ptr(&self) -> NonNull<[u8]> {
+        self.ptr
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Grows the allocation to `new_size` bytes, in place if possible and by
+    /// moving it otherwise. Returns `false` and leaves the allocation
+    /// unchanged if neither works.
+    pub fn grow(&mut self, new_size: usize) -> bool {
+        assert!(new_size >= self.layout.size());
+        self.resize(new_size)
+    }
+
+    /// Shrinks the allocation to `new_size` bytes, in place if possible and
+    /// by moving it otherwise. Returns `false` and leaves the allocation
+    /// unchanged if neither works.
+    pub fn shrink(&mut self, new_size: usize) -> bool {
+        assert!(new_size <= self.layout.size());
+        self.resize(new_size)
+    }
+
+    fn resize(&mut self, new_size: usize) -> bool {
+        let Ok(new_layout) = Layout::from_size_align(new_size, self.layout.align()) else {
+            return false;
+        };
+        let ptr = self.ptr.as_mut_ptr();
+        let Some(new) = (unsafe { self.alloc.realloc(ptr, self.layout, new_size) }) else {
+            return false;
+        };
+        self.ptr = new;
+        self.layout = new_layout;
+        true
+    }
+
+    /// Releases the allocation from the handle without freeing it.
+    pub fn into_raw(self) -> (NonNull<[u8]>, Layout) {
+        let raw = (self.ptr, self.layout);
+        mem::forget(self);
+        raw
+    }
+}
+
+impl<A: Allocator> Drop for GrowableAlloc<'_, A> {
+    fn drop(&mut self) {
+        unsafe { self.alloc.dealloc(self.ptr.as_mut_ptr(), self.layout) }
+    }
+}
+
+#[cfg(all(test, feature = "bump"))]
+mod tests {
+    use core::{
+        alloc::Layout,
+        cell::SyncUnsafeCell,
+        ptr::{addr_of_mut, slice_from_raw_parts_mut, NonNull},
+    };
+
+    use crate::{bump, Allocator};
+
+    #[repr(align(8))]
+    struct MemPool<const N: usize>([u8; N]);
+
+    #[test]
+    fn test() {
+        const HEAP_SIZE: usize = 1 << 6;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let mut alloc = bump::Allocator::new(
+            NonNull::new(slice_from_raw_parts_mut(
+                unsafe { addr_of_mut!((*HEAP.get()).0) }.cast(),
+                HEAP_SIZE,
+            ))
+            .unwrap(),
+        );
+        let mut handle = unsafe { alloc.alloc_growable(Layout::new::<[u8; 4]>()) }.unwrap();
+        let start = handle.as_ptr().as_mut_ptr();
+        unsafe { start.copy_from_nonoverlapping([1, 2, 3, 4].as_ptr(), 4) };
+
+        // the handle is the top allocation, so it grows in place
+        for size in [8, 16, 24] {
+            assert!(handle.grow(size));
+            assert_eq!(handle.as_ptr().as_mut_ptr(), start);
+            assert_eq!(handle.layout().size(), size);
+        }
+
+        // growing past the end of the region would need a copy, for which
+        // there is no room left either
+        assert!(!handle.grow(HEAP_SIZE + 1));
+        assert_eq!(handle.layout().size(), 24);
+        let (ptr, layout) = handle.into_raw();
+
+        let other = unsafe { alloc.alloc(Layout::new::<u8>()) }.unwrap();
+        let mut handle = crate::growable::GrowableAlloc::new(&mut alloc, ptr, layout);
+        // the handle is no longer the top allocation, so it is moved
+        assert!(handle.grow(32));
+        let moved = handle.as_ptr().as_mut_ptr();
+        assert!(moved > other.as_mut_ptr());
+        assert_eq!(unsafe { *moved.cast::<[u8; 4]>() }, [1, 2, 3, 4]);
+    }
+}
@@ -1,4 +1,5 @@
 #![no_std]
+#![feature(allocator_api)]
 #![feature(sync_unsafe_cell)]
 #![feature(strict_provenance)]
 #![feature(slice_ptr_len, slice_ptr_get)]
@@ -8,12 +9,422 @@
 #![deny(unsafe_op_in_unsafe_fn)]
 #![warn(clippy::as_conversions)]
 
-use core::{alloc::Layout, ptr::NonNull};
+#[cfg(test)]
+extern crate std;
 
+use core::{
+    alloc::Layout,
+    mem,
+    ptr::{self, NonNull},
+};
+
+#[cfg(feature = "bump")]
 pub mod bump;
+pub mod counting;
+pub mod epoch;
+#[cfg(feature = "linked_list")]
+pub mod fixed_size_block;
+pub mod frame;
+pub mod growable;
+#[cfg(feature = "linked_list")]
 pub mod linked_list;
+pub mod locked;
+pub mod null;
+pub mod stack;
+#[cfg(all(test, feature = "linked_list"))]
+mod test_support;
+pub mod tracked;
+pub mod tree;
+pub mod workload;
+
+/// The size of a page, as used by page-granular allocations.
+pub const PAGE_SIZE: usize = 1 << 12;
+
+/// The hook that [`alloc_fail`] reports to before panicking.
+static LOG_HOOK: locked::Locked<Option<fn(&str)>> = locked::Locked::new(None);
+
+/// Sets a hook that is passed the message of any assertion in the allocators
+/// that fails, before the panic. A kernel can use it to get the message out
+/// over serial even if panicking does not.
+pub fn set_log_hook(hook: Option<fn(&str)>) {
+    *LOG_HOOK.lock() = hook;
+}
+
+/// Reports `msg` to the log hook, if any, and panics with it.
+#[cold]
+pub(crate) fn alloc_fail(msg: &str) -> ! {
+    let hook = *LOG_HOOK.lock();
+    if let Some(hook) = hook {
+        hook(msg);
+    }
+    panic!("{msg}")
+}
+
+/// Like `assert!`, but goes through [`alloc_fail`].
+#[inline(always)]
+pub(crate) fn alloc_assert(cond: bool, msg: &str) {
+    if !cond {
+        alloc_fail(msg);
+    }
+}
+
+/// Asserts a condition that the allocator's own bookkeeping guarantees.
+///
+/// With the `unsafe_no_panic` feature the condition is assumed rather than
+/// checked, so that the allocation paths cannot panic.
+///
+/// This function is unsafe because a false `cond` is undefined behaviour with
+/// that feature.
+#[cfg(feature = "linked_list")]
+#[inline(always)]
+pub(crate) unsafe fn invariant(cond: bool) {
+    if cfg!(feature = "unsafe_no_panic") {
+        if !cond {
+            unsafe { core::hint::unreachable_unchecked() }
+        }
+    } else {
+        alloc_assert(cond, "allocator invariant violated");
+    }
+}
 
-unsafe trait Allocator {
+/// Unwraps an `Option` that the allocator's own bookkeeping guarantees to be
+/// `Some`, like [`invariant`].
+///
+/// This function is unsafe because `None` is undefined behaviour with the
+/// `unsafe_no_panic` feature.
+#[cfg(feature = "linked_list")]
+#[inline(always)]
+pub(crate) unsafe fn unwrap_invariant<T>(option: Option<T>) -> T {
+    unsafe { invariant(option.is_some()) };
+    match option {
+        Some(value) => value,
+        None => unsafe { core::hint::unreachable_unchecked() },
+    }
+}
+
+/// Returns the allocation handed out for a zero-sized `layout`: an empty
+/// slice at the address `layout.align()`, which is aligned and non-null but
+/// uses none of the allocator's memory.
+#[cfg(any(feature = "bump", feature = "linked_list"))]
+pub(crate) fn dangling(layout: Layout) -> NonNull<[u8]> {
+    let ptr = ptr::null_mut::<u8>().with_addr(layout.align());
+    unsafe { NonNull::new_unchecked(ptr::slice_from_raw_parts_mut(ptr, 0)) }
+}
+
+/// The reason an allocation failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AllocError {
+    /// No free memory can satisfy the request.
+    OutOfMemory,
+    /// The request is larger than the allocator allows a single allocation
+    /// to be.
+    ExceedsLimit,
+    /// The allocator already has as many outstanding allocations as it
+    /// allows.
+    TooManyAllocations,
+    /// The request, once padded for the allocator's own use, would be larger
+    /// than `isize::MAX` bytes.
+    LayoutOverflow,
+    /// The allocator gave up looking for free memory after as many steps as
+    /// it allows a single allocation.
+    BudgetExceeded,
+    /// Every free region that fits would have to be split into more free
+    /// regions than the allocator allows.
+    FragmentationLimit,
+}
+
+/// Ignoring the result of an allocation is an error when `unused_must_use` is
+/// denied:
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// # use core::{alloc::Layout, ptr::NonNull};
+/// use allocator::{bump, Allocator};
+///
+/// let mut heap = [0u8; 16];
+/// let mut alloc = bump::Allocator::new(NonNull::from(&mut heap[..]));
+/// unsafe { alloc.alloc(Layout::new::<u64>()) };
+/// ```
+///
+/// # Safety
+///
+/// Implementations must only return allocations that are valid for the
+/// requested layout and do not overlap any other live allocation.
+pub unsafe trait Allocator {
+    /// # Safety
+    ///
+    /// The memory managed by the allocator must be valid and unused by
+    /// anything else.
+    #[must_use = "allocation may fail; handle the None/Err"]
     unsafe fn alloc(&mut self, layout: Layout) -> Option<NonNull<[u8]>>;
+
+    /// Like [`Allocator::alloc`], but reports why the allocation failed.
+    ///
+    /// # Safety
+    ///
+    /// See [`Allocator::alloc`].
+    #[must_use = "allocation may fail; handle the None/Err"]
+    unsafe fn try_alloc(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        unsafe { self.alloc(layout) }.ok_or(AllocError::OutOfMemory)
+    }
+
+    /// Like [`Allocator::alloc`], but the returned memory is zeroed.
+    ///
+    /// # Safety
+    ///
+    /// See [`Allocator::alloc`].
+    #[must_use = "allocation may fail; handle the None/Err"]
+    unsafe fn alloc_zeroed(&mut self, layout: Layout) -> Option<NonNull<[u8]>> {
+        let ptr = unsafe { self.alloc(layout) }?;
+        unsafe { ptr.as_mut_ptr().write_bytes(0, ptr.len()) };
+        Some(ptr)
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must be a live allocation from this allocator made with `layout`.
     unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout);
+
+    /// Frees the allocation `*slot` points to, if it is not null, and nulls
+    /// out `slot`, so that freeing the same slot again does nothing.
+    ///
+    /// # Safety
+    ///
+    /// `*slot` must be null or a live allocation from this allocator made
+    /// with `layout`.
+    unsafe fn dealloc_and_invalidate(&mut self, slot: &mut *mut u8, layout: Layout) {
+        let ptr = mem::replace(slot, ptr::null_mut());
+        if !ptr.is_null() {
+            unsafe { self.dealloc(ptr, layout) }
+        }
+    }
+
+    /// Frees `ptr` without any of the validation done by `dealloc`.
+    ///
+    /// # Safety
+    ///
+    /// The caller takes full responsibility for `ptr` and `layout` describing
+    /// a live allocation from this allocator.
+    unsafe fn dealloc_unchecked(&mut self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.dealloc(ptr, layout) }
+    }
+
+    /// Returns how many bytes an allocation made for `layout` can actually
+    /// use, which is at least `layout.size()`.
+    fn usable_size(&self, layout: Layout) -> usize {
+        layout.size()
+    }
+
+    /// Tries to extend the allocation at `ptr` to `new_size` bytes without
+    /// moving it, returning whether it succeeded.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a live allocation from this allocator made with `layout`.
+    unsafe fn grow_in_place(&mut self, _ptr: *mut u8, _layout: Layout, _new_size: usize) -> bool {
+        false
+    }
+
+    /// Tries to shrink the allocation at `ptr` to `new_size` bytes without
+    /// moving it, returning whether it succeeded.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a live allocation from this allocator made with `layout`.
+    unsafe fn shrink_in_place(&mut self, _ptr: *mut u8, _layout: Layout, _new_size: usize) -> bool {
+        false
+    }
+
+    /// Like [`Allocator::alloc`], but for moving the allocation at `ptr`
+    /// into, so the allocator may prefer memory close to it.
+    ///
+    /// # Safety
+    ///
+    /// See [`Allocator::alloc`]. `ptr` must be a live allocation from this
+    /// allocator.
+    #[must_use = "allocation may fail; handle the None/Err"]
+    unsafe fn alloc_near(&mut self, _ptr: *mut u8, layout: Layout) -> Option<NonNull<[u8]>> {
+        unsafe { self.alloc(layout) }
+    }
+
+    /// Resizes the allocation at `ptr` to `new_size` bytes, keeping the
+    /// alignment. The allocation is shrunk or grown in place if possible, and
+    /// otherwise moved to a new allocation, copying over the contents that
+    /// fit.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a live allocation from this allocator made with `layout`.
+    #[must_use = "allocation may fail; handle the None/Err"]
+    unsafe fn realloc(
+        &mut self,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> Option<NonNull<[u8]>> {
+        let new_layout = Layout::from_size_align(new_size, layout.align()).ok()?;
+        unsafe { self.realloc_aligned(ptr, layout, new_layout) }
+    }
+
+    /// Like [`Allocator::realloc`], but the new allocation is made for
+    /// `new_layout`, alignment included. Resizing in place is only tried if
+    /// the allocation is already aligned enough. If the allocator would give
+    /// the new layout a block of the same size, the allocation is left as it
+    /// is.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a live allocation from this allocator made with
+    /// `old_layout`.
+    #[must_use = "allocation may fail; handle the None/Err"]
+    unsafe fn realloc_aligned(
+        &mut self,
+        ptr: *mut u8,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Option<NonNull<[u8]>> {
+        let new_size = new_layout.size();
+        if new_layout.align() <= old_layout.align() {
+            let size = self.usable_size(new_layout);
+            if size == self.usable_size(old_layout) {
+                return NonNull::new(ptr::slice_from_raw_parts_mut(ptr, size));
+            }
+            let in_place = if new_size <= old_layout.size() {
+                unsafe { self.shrink_in_place(ptr, old_layout, new_size) }
+            } else {
+                unsafe { self.grow_in_place(ptr, old_layout, new_size) }
+            };
+            if in_place {
+                return NonNull::new(ptr::slice_from_raw_parts_mut(ptr, size));
+            }
+        }
+        let new = unsafe { self.alloc_near(ptr, new_layout) }?;
+        unsafe {
+            ptr::copy_nonoverlapping(ptr, new.as_mut_ptr(), Ord::min(old_layout.size(), new_size));
+            self.dealloc(ptr, old_layout);
+        }
+        Some(new)
+    }
+
+    /// Allocates an array of at least `min` `T`s and returns it along with how
+    /// many `T`s actually fit in the block it got, which can be more than
+    /// `min` if the allocator rounds the block up. The array is freed with the
+    /// layout of `min` `T`s.
+    ///
+    /// # Safety
+    ///
+    /// See [`Allocator::alloc`].
+    #[must_use = "allocation may fail; handle the None/Err"]
+    unsafe fn alloc_array_with_capacity<T>(&mut self, min: usize) -> Option<(NonNull<T>, usize)>
+    where
+        Self: Sized,
+    {
+        let ptr = unsafe { self.alloc(Layout::array::<T>(min).ok()?) }?;
+        let capacity = ptr
+            .len()
+            .checked_div(mem::size_of::<T>())
+            .unwrap_or(usize::MAX);
+        Some((ptr.cast(), capacity))
+    }
+
+    /// Allocates memory for `layout` and returns a handle that remembers the
+    /// layout, so that the allocation can be resized without passing it again.
+    ///
+    /// # Safety
+    ///
+    /// See [`Allocator::alloc`].
+    #[must_use = "allocation may fail; handle the None/Err"]
+    unsafe fn alloc_growable(&mut self, layout: Layout) -> Option<growable::GrowableAlloc<'_, Self>>
+    where
+        Self: Sized,
+    {
+        let ptr = unsafe { self.alloc(layout) }?;
+        Some(growable::GrowableAlloc::new(self, ptr, layout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "bump")]
+    use core::{alloc::Layout, ptr};
+    use std::{panic, string::String, vec::Vec};
+
+    use super::{alloc_assert, locked::Locked, set_log_hook};
+    #[cfg(feature = "bump")]
+    use super::{bump, counting, Allocator as _};
+
+    #[test]
+    fn log_hook() {
+        static LOGGED: Locked<Vec<String>> = Locked::new(Vec::new());
+        set_log_hook(Some(|msg| LOGGED.lock().push(msg.into())));
+        alloc_assert(true, "not logged");
+        let result = panic::catch_unwind(|| alloc_assert(false, "forced"));
+        set_log_hook(None);
+        assert!(result.is_err());
+        let logged = LOGGED.lock();
+        assert!(logged.iter().any(|msg| msg == "forced"));
+        assert!(!logged.iter().any(|msg| msg == "not logged"));
+    }
+
+    #[cfg(feature = "bump")]
+    #[test]
+    fn dealloc_and_invalidate() {
+        let mut heap = [0u64; 2];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let mut alloc = counting::Allocator::new(bump::Allocator::from_slice(heap));
+        let l = Layout::new::<u64>();
+        let mut slot = unsafe { alloc.alloc(l) }.unwrap().as_mut_ptr();
+        unsafe { alloc.dealloc_and_invalidate(&mut slot, l) };
+        assert_eq!(slot, ptr::null_mut());
+        unsafe { alloc.dealloc_and_invalidate(&mut slot, l) };
+        assert_eq!(alloc.counters().dealloc_calls(), 1);
+    }
+
+    #[cfg(feature = "linked_list")]
+    #[test]
+    fn alloc_array_with_capacity() {
+        use core::{alloc::Layout, ptr::NonNull};
+
+        use super::{linked_list, Allocator as _};
+
+        let mut heap = [0u64; 8];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let mut alloc = linked_list::Allocator::new();
+        unsafe {
+            alloc.add_free_region(NonNull::from(heap));
+            let (p, capacity) = alloc.alloc_array_with_capacity::<u32>(3).unwrap();
+            // twelve bytes are rounded up to a whole free list node
+            assert_eq!(capacity, alloc.usable_size(Layout::new::<[u32; 3]>()) / 4);
+            assert_eq!(capacity, 4);
+            p.as_ptr().add(capacity - 1).write(1);
+            alloc.dealloc(p.as_ptr().cast(), Layout::new::<[u32; 3]>());
+
+            let (_, capacity) = alloc.alloc_array_with_capacity::<()>(3).unwrap();
+            assert_eq!(capacity, usize::MAX);
+        }
+    }
+}
+
+/// Only built with the bump allocator but not the linked list allocator, to
+/// check that nothing else in the crate depends on the latter.
+#[cfg(all(test, feature = "bump", not(feature = "linked_list")))]
+mod bump_only {
+    use core::alloc::Layout;
+
+    use super::{bump, counting, Allocator as _};
+
+    #[test]
+    fn bump_only() {
+        let mut heap = [0u64; 4];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let mut alloc = counting::Allocator::new(bump::Allocator::from_slice(heap));
+        let l = Layout::new::<u64>();
+        unsafe {
+            let p = alloc.alloc(l).unwrap();
+            let p = alloc.realloc(p.as_mut_ptr(), l, 16).unwrap();
+            alloc.dealloc(p.as_mut_ptr(), Layout::new::<[u64; 2]>());
+        }
+        assert_eq!(alloc.counters().live_bytes(), 0);
+    }
 }
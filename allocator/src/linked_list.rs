@@ -1,17 +1,68 @@
+#[cfg(debug_assertions)]
+use core::panic::Location;
 use core::{
     alloc::Layout,
+    cell::Cell,
+    fmt, iter,
+    marker::PhantomData,
     mem,
     ptr::{self, addr_of_mut, NonNull},
 };
 
 use ptr_ext::PtrExt;
 
+#[cfg(feature = "bump")]
+use crate::bump;
+use crate::{alloc_assert, alloc_fail, invariant, unwrap_invariant, AllocError, PAGE_SIZE};
+
 // based off https://os.phil-opp.com/allocator-designs/#linked-list-allocator
 
+/// The most memory regions that can be added to an allocator.
+pub const MAX_REGIONS: usize = 16;
+
 pub struct Allocator {
     head: Node,
+    // each region added, in the order they were added
+    regions: [Option<NonNull<[u8]>>; MAX_REGIONS],
+    // for each region, the address from which it has never been handed out
+    // and is still zeroed, apart from the nodes of free regions
+    pristine: [usize; MAX_REGIONS],
+    // for each slot of the region table, how often a region was taken out of
+    // it, so that the ids of those regions do not match the next one
+    generations: [u32; MAX_REGIONS],
+    region_count: usize,
+    coalesce: bool,
+    no_split: bool,
+    grow_down: bool,
+    min_useful_remainder: usize,
+    // bytes left attached to live allocations beyond the padded size of the
+    // requests
+    attached_bytes: u64,
+    // the tails holding those bytes, most recent first
+    tails: *mut Tail,
+    strategy: Strategy,
+    poison: Option<u8>,
+    max_alloc: Option<usize>,
+    max_search_steps: Option<usize>,
+    max_free_blocks: Option<usize>,
+    guard_bytes: usize,
+    // the guards after live allocations, most recent first
+    guards: Option<NonNull<Guard>>,
+    allocations: u64,
+    max_allocations: Option<u64>,
+    defrag_cursor: usize,
+    // start of the free region the last deallocation ended up in
+    recent: Option<usize>,
+    #[cfg(feature = "profiling")]
+    tagged: HeaderList<u32>,
+    #[cfg(debug_assertions)]
+    traced: HeaderList<&'static Location<'static>>,
 }
 
+// SAFETY: the free list nodes live in memory handed over to the allocator, so
+// they move between threads together with it
+unsafe impl Send for Allocator {}
+
 impl Allocator {
     /// Creates an empty Allocator.
     pub const fn new() -> Self {
@@ -20,177 +71,4548 @@ impl Allocator {
                 size: 0,
                 next: None,
             },
+            regions: [None; MAX_REGIONS],
+            pristine: [0; MAX_REGIONS],
+            generations: [0; MAX_REGIONS],
+            region_count: 0,
+            coalesce: false,
+            no_split: false,
+            grow_down: false,
+            min_useful_remainder: mem::size_of::<Node>(),
+            attached_bytes: 0,
+            tails: ptr::null_mut(),
+            strategy: Strategy::FirstFit,
+            poison: None,
+            max_alloc: None,
+            max_search_steps: None,
+            max_free_blocks: None,
+            guard_bytes: 0,
+            guards: None,
+            allocations: 0,
+            max_allocations: None,
+            defrag_cursor: 0,
+            recent: None,
+            #[cfg(feature = "profiling")]
+            tagged: HeaderList::new(),
+            #[cfg(debug_assertions)]
+            traced: HeaderList::new(),
+        }
+    }
+
+    /// Creates an Allocator whose free list starts out as the whole of
+    /// `slice`.
+    ///
+    /// Panics if `slice` is too small or misaligned to hold a free list node.
+    pub fn from_slice(slice: &'static mut [u8]) -> Self {
+        let mut alloc = Self::new();
+        unsafe { alloc.add_free_region(NonNull::from(slice)) };
+        alloc
+    }
+
+    /// Returns a builder for an Allocator with the given options.
+    pub const fn builder() -> Builder {
+        Builder(Self::new())
+    }
+
+    /// Creates an empty Allocator that merges freed memory with adjacent free
+    /// regions.
+    pub const fn coalescing() -> Self {
+        Self {
+            coalesce: true,
+            ..Self::new()
+        }
+    }
+
+    /// Creates an empty Allocator that never splits a free region, handing
+    /// out the whole region instead. The rest of the region stays attached
+    /// to the allocation until it is freed.
+    ///
+    /// With requests of a single size, regions are reused as they are instead
+    /// of being cut up into slivers, like a pool.
+    pub const fn no_split() -> Self {
+        Self {
+            no_split: true,
+            ..Self::new()
         }
     }
 
+    /// Returns whether freed memory is merged with adjacent free regions.
+    pub fn is_coalescing(&self) -> bool {
+        self.coalesce
+    }
+
+    /// Returns whether free regions are handed out whole rather than split.
+    pub fn is_no_split(&self) -> bool {
+        self.no_split
+    }
+
+    /// Returns whether allocations are placed at the top of free regions.
+    pub fn is_grow_down(&self) -> bool {
+        self.grow_down
+    }
+
+    /// Returns the smallest remainder of a free region that is split off an
+    /// allocation as a free region of its own.
+    pub fn min_useful_remainder(&self) -> usize {
+        self.min_useful_remainder
+    }
+
+    /// Returns the number of bytes that live allocations take up beyond their
+    /// padded size, because the rest of a free region was left attached to
+    /// them rather than split off. This is internal fragmentation, which
+    /// freeing the allocations gives back.
+    pub fn attached_bytes(&self) -> u64 {
+        self.attached_bytes
+    }
+
+    /// Returns how a free region is picked for an allocation.
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Returns the byte that freed memory is filled with, if any.
+    pub fn poison(&self) -> Option<u8> {
+        self.poison
+    }
+
+    /// Returns the largest size a single allocation may request, if capped.
+    pub fn max_alloc(&self) -> Option<usize> {
+        self.max_alloc
+    }
+
+    /// Returns the most free regions an allocation may examine before giving
+    /// up, if capped.
+    pub fn max_search_steps(&self) -> Option<usize> {
+        self.max_search_steps
+    }
+
+    /// Returns the most free regions an allocation may leave behind by
+    /// splitting one, if capped.
+    pub fn max_free_blocks(&self) -> Option<usize> {
+        self.max_free_blocks
+    }
+
+    /// Returns how many bytes of guard follow each allocation.
+    pub fn guard_bytes(&self) -> usize {
+        self.guard_bytes
+    }
+
+    /// Returns the number of outstanding allocations.
+    pub fn allocation_count(&self) -> u64 {
+        self.allocations
+    }
+
+    /// Returns the most allocations that may be outstanding at once, if
+    /// capped.
+    pub fn max_allocations(&self) -> Option<u64> {
+        self.max_allocations
+    }
+
     /// Adds the given memory region to the front of the list.
     ///
+    /// Allocations carry no header, so a region aligned to `Node` can be
+    /// handed out whole by a single request for all of its bytes, and
+    /// freeing that allocation leaves the region as one free block again.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that the given
+    /// memory region is valid and unused.
+    pub unsafe fn add_free_region(&mut self, region: NonNull<[u8]>) -> RegionId {
+        let added = unsafe { self.try_add_free_region(region) };
+        alloc_assert(added.is_ok(), "invalid free region");
+        unsafe { unwrap_invariant(added.ok()) }
+    }
+
+    /// Adds the given memory region to the front of the list, or returns why
+    /// it cannot be used as a free region. A region of exactly
+    /// `size_of::<Node>()` bytes is accepted.
+    ///
+    /// # Safety
+    ///
     /// This function is unsafe because the caller must guarantee that the given
     /// memory region is valid and unused.
-    pub unsafe fn add_free_region(&mut self, region: NonNull<[u8]>) {
-        assert!(region.as_mut_ptr().is_aligned_to(mem::align_of::<Node>()));
-        assert!(region.len() >= mem::size_of::<Node>());
+    pub unsafe fn try_add_free_region(
+        &mut self,
+        region: NonNull<[u8]>,
+    ) -> Result<RegionId, RegionError> {
+        if !region.as_mut_ptr().is_aligned_to(mem::align_of::<Node>()) {
+            return Err(RegionError::Misaligned);
+        }
+        if region.len() < mem::size_of::<Node>() {
+            return Err(RegionError::TooSmall);
+        }
+        if region.addr().get().checked_add(region.len()).is_none() {
+            return Err(RegionError::Overflow);
+        }
+        let Some(slot) = self.free_region_slot() else {
+            return Err(RegionError::TooManyRegions);
+        };
+        self.regions[slot] = Some(region);
+        self.pristine[slot] = region.addr().get() + region.len();
+        self.region_count += 1;
+        unsafe {
+            self.poison_freed(region.as_mut_ptr(), region.len());
+            self.push_region(region);
+        }
+        Ok(self.region_id(slot))
+    }
+
+    /// Like [`add_free_region`](Allocator::add_free_region), but for a region
+    /// that is known to be zeroed, such as a zero-initialized static, so that
+    /// [`alloc_zeroed`](super::Allocator::alloc_zeroed) need not zero memory
+    /// from it again until it has been handed out once. Poisoning allocators
+    /// fill the region with the poison byte like any other.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that the given
+    /// memory region is valid, unused and zeroed.
+    pub unsafe fn add_zeroed_region(&mut self, region: NonNull<[u8]>) -> RegionId {
+        let id = unsafe { self.add_free_region(region) };
+        if self.poison.is_none() {
+            self.pristine[id.slot] = region.addr().get();
+        }
+        id
+    }
 
+    /// Returns the start of the memory region if the allocator has exactly
+    /// one, as several regions have no single span.
+    pub fn heap_start(&self) -> Option<usize> {
+        self.single_region().map(|region| region.addr().get())
+    }
+
+    /// Returns the end of the memory region if the allocator has exactly one,
+    /// as several regions have no single span.
+    pub fn heap_end(&self) -> Option<usize> {
+        self.single_region()
+            .map(|region| region.addr().get() + region.len())
+    }
+
+    fn single_region(&self) -> Option<NonNull<[u8]>> {
+        if self.region_count != 1 {
+            return None;
+        }
+        self.added_regions().next()
+    }
+
+    /// Returns the slot of the added memory region containing `addr`.
+    fn region_slot_of(&self, addr: usize) -> Option<usize> {
+        self.regions.iter().position(|region| {
+            region.is_some_and(|region| {
+                (region.addr().get()..region.addr().get() + region.len()).contains(&addr)
+            })
+        })
+    }
+
+    /// Returns whether the memory at `addr` has never been handed out, so
+    /// that it is zeroed unless a free list node is written there.
+    fn is_pristine(&self, addr: usize) -> bool {
+        self.region_slot_of(addr)
+            .is_some_and(|slot| addr >= self.pristine[slot])
+    }
+
+    /// Records that the memory from `start` up to `end` has been handed out.
+    fn mark_used(&mut self, start: usize, end: usize) {
+        if let Some(slot) = self.region_slot_of(start) {
+            self.pristine[slot] = Ord::max(self.pristine[slot], end);
+        }
+    }
+
+    /// Returns the id of the memory region in `slot` of the region table.
+    fn region_id(&self, slot: usize) -> RegionId {
+        RegionId {
+            slot,
+            generation: self.generations[slot],
+        }
+    }
+
+    /// Returns the memory region `id`, or `None` if it has been forgotten.
+    fn region(&self, id: RegionId) -> Option<NonNull<[u8]>> {
+        if *self.generations.get(id.slot)? != id.generation {
+            return None;
+        }
+        self.regions[id.slot]
+    }
+
+    /// Removes the memory region `id` from the allocator, along with its
+    /// free regions, and returns it, or `None` if there is no such region.
+    /// Its id is never handed out again, so a stale id matches no region.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that there
+    /// are no outstanding allocations in the region.
+    pub unsafe fn forget_region(&mut self, id: RegionId) -> Option<NonNull<[u8]>> {
+        let region = self.region(id)?;
+        self.regions[id.slot] = None;
+        self.generations[id.slot] = self.generations[id.slot].wrapping_add(1);
+        self.region_count -= 1;
+        let range = region.addr().get()..region.addr().get() + region.len();
+        let mut curr = addr_of_mut!(self.head);
+        while let Some(node) = unsafe { (*curr).next } {
+            if range.contains(&node.addr().get()) {
+                unsafe { (*curr).next = (*node.as_ptr()).next.take() };
+            } else {
+                curr = node.as_ptr();
+            }
+        }
+        self.defrag_cursor = 0;
+        self.recent = None;
+        Some(region)
+    }
+
+    /// Returns the first slot of the region table that is not in use.
+    fn free_region_slot(&self) -> Option<usize> {
+        self.regions.iter().position(Option::is_none)
+    }
+
+    /// Pushes the given memory region to the front of the list without
+    /// checking that it can hold a `Node`.
+    unsafe fn push_region(&mut self, region: NonNull<[u8]>) {
         let node = Node {
             size: region.len(),
             next: self.head.next.take(),
         };
         let node_ptr = region.cast::<Node>();
+        // catch any path that produces a misaligned node here rather than
+        // letting it corrupt memory
+        debug_assert!(
+            node_ptr.as_ptr().is_aligned(),
+            "misaligned free list node at {node_ptr:p}"
+        );
         unsafe {
             node_ptr.as_ptr().write(node);
         }
         self.head.next = Some(node_ptr);
     }
 
-    /// Looks for a free region with the given size and alignment and removes
-    /// it from the list.
+    /// Looks for a free region with the given size and alignment, picked
+    /// according to the strategy, and removes it from the list. Only
+    /// allocations placed as `placement` allows are considered.
     ///
-    /// Returns a tuple of the list node and a slice pointing to the allocation
-    fn find_region(&mut self, layout: Layout) -> Option<(NonNull<Node>, NonNull<[u8]>)> {
+    /// Returns a tuple of the list node and a slice pointing to the allocation,
+    /// or [`AllocError::BudgetExceeded`] if it gave up after
+    /// [`max_search_steps`](Builder::max_search_steps), or
+    /// [`AllocError::FragmentationLimit`] if only regions that would be split
+    /// into more than [`max_free_blocks`](Builder::max_free_blocks) fit.
+    fn find_region(
+        &mut self,
+        layout: Layout,
+        placement: Placement,
+    ) -> Result<(NonNull<Node>, NonNull<[u8]>), AllocError> {
+        let min_remainder = (!self.no_split).then_some(self.min_useful_remainder);
+        let grow_down = self.grow_down
+            && placement.boundary.is_none()
+            && placement.min_addr == Placement::ANYWHERE.min_addr
+            && placement.max_addr == Placement::ANYWHERE.max_addr;
+        let place = |region| {
+            if grow_down {
+                return Node::alloc_from_top(region, layout, min_remainder);
+            }
+            Node::alloc_from_region(region, layout, min_remainder, placement.boundary).filter(
+                |alloc| {
+                    alloc.addr().get() >= placement.min_addr
+                        && alloc.addr().get() + alloc.len() <= placement.max_addr
+                },
+            )
+        };
+        // only a region split into three adds a free region, so those are
+        // passed over once there are as many as allowed
+        let at_limit = self
+            .max_free_blocks
+            .is_some_and(|max| self.free_block_count() >= max);
+        let limited = Cell::new(false);
+        let alloc_from = |region: *mut Node| {
+            let alloc = place(region)?;
+            let start = alloc.addr().get();
+            if at_limit && start > region.addr() && start + alloc.len() < Node::end(region).addr() {
+                limited.set(true);
+                return None;
+            }
+            Some(alloc)
+        };
+        // growing down looks for the highest region rather than the first
+        let scan_all = self.strategy == Strategy::BestFit
+            || (grow_down && self.strategy == Strategy::FirstFit);
+        let best_fit = self.strategy == Strategy::BestFit;
+        // lower is better: the size for best fit, then the address
+        let rank = |node: *mut Node| {
+            let size = if best_fit { unsafe { (*node).size } } else { 0 };
+            (size, if grow_down { !node.addr() } else { node.addr() })
+        };
+        // the node before the region picked so far, the region and the
+        // allocation carved out of it
+        let mut found: Option<(*mut Node, *mut Node, NonNull<[u8]>)> = None;
+        if self.strategy == Strategy::RecentFirst {
+            // the recorded region may since have been allocated from or merged
+            // away, in which case it is no longer found at that address
+            if let Some(prev) = self.recent.and_then(|recent| self.find_prev(recent)) {
+                let region = unsafe { unwrap_invariant((*prev).next).as_ptr() };
+                if let Some(alloc) = alloc_from(region) {
+                    found = Some((prev, region, alloc));
+                }
+            }
+        }
         let mut curr = addr_of_mut!(self.head);
-        while let Some(region) = unsafe { (*curr).next } {
+        let mut steps = 0;
+        while found.is_none() || scan_all {
+            let Some(region) = (unsafe { (*curr).next }) else {
+                break;
+            };
+            if matches!(self.max_search_steps, Some(max) if steps >= max) {
+                if found.is_some() {
+                    break;
+                }
+                return Err(AllocError::BudgetExceeded);
+            }
+            steps += 1;
             let region = region.as_ptr();
-            if let Some(alloc) = Node::alloc_from_region(region, layout) {
-                let next = unsafe { (*region).next.take() };
-                let node = mem::replace(unsafe { &mut (*curr).next }, next).unwrap();
-                assert_eq!(node.as_ptr(), region);
-                return Some((node, alloc));
-            } else {
-                curr = region;
+            if let Some(alloc) = alloc_from(region) {
+                let better = match found {
+                    Some((_, best, _)) => rank(region) < rank(best),
+                    None => true,
+                };
+                if better {
+                    found = Some((curr, region, alloc));
+                }
             }
+            curr = region;
         }
-        None
+        let (prev, region, alloc) = found.ok_or(if limited.get() {
+            AllocError::FragmentationLimit
+        } else {
+            AllocError::OutOfMemory
+        })?;
+        let next = unsafe { (*region).next.take() };
+        let node = unsafe {
+            let node = unwrap_invariant(mem::replace(&mut (*prev).next, next));
+            invariant(node.as_ptr() == region);
+            node
+        };
+        Ok((node, alloc))
     }
 
-    /// Adjust the given layout so that the resulting allocated memory
-    /// region is also capable of storing a `Node`.
-    fn adjust(layout: Layout) -> Layout {
-        let layout = layout
-            .align_to(mem::align_of::<Node>())
-            .expect("adjusting alignment failed")
-            .pad_to_align();
-        Layout::from_size_align(
-            Ord::max(layout.size(), mem::size_of::<Node>()),
-            layout.align(),
-        )
-        .unwrap()
+    /// Fills the freed allocation at `ptr` with the poison byte, if any.
+    ///
+    /// This function is unsafe because the caller must guarantee that `ptr` is
+    /// valid for writes of `size` bytes.
+    unsafe fn poison_freed(&self, ptr: *mut u8, size: usize) {
+        if let Some(byte) = self.poison {
+            unsafe { ptr.write_bytes(byte, size) };
+        }
     }
-}
 
-unsafe impl super::Allocator for Allocator {
-    unsafe fn alloc(&mut self, layout: Layout) -> Option<NonNull<[u8]>> {
-        let layout = Allocator::adjust(layout);
-        self.find_region(layout).map(|(region, alloc)| {
-            let alloc_end = alloc
-                .as_ptr()
-                .as_mut_ptr()
-                .map_addr(|addr| addr + alloc.len());
-            let excess_size = Node::end(region.as_ptr()).addr() - alloc_end.addr();
-            if excess_size > 0 {
-                unsafe {
-                    // SAFETY: alloc has provenance for entire memory region pointed to by region
-                    self.add_free_region(
-                        NonNull::new(ptr::slice_from_raw_parts_mut(alloc_end, excess_size))
-                            .unwrap(),
-                    );
-                }
-            }
-            alloc
-        })
+    /// Poisons the header of a free region that has just been merged into
+    /// the one in front of it, so that all but the header of every free
+    /// region holds the poison byte. Without poisoning, the header is zeroed
+    /// if it is in pristine memory, which has to stay zeroed.
+    ///
+    /// This function is unsafe because the caller must guarantee that `node`
+    /// is no longer in the list.
+    unsafe fn poison_merged(&self, node: *mut Node) {
+        if self.poison.is_none() && self.is_pristine(node.addr()) {
+            unsafe { node.cast::<u8>().write_bytes(0, mem::size_of::<Node>()) };
+        }
+        unsafe { self.poison_freed(node.cast(), mem::size_of::<Node>()) };
     }
 
-    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
-        let layout = Allocator::adjust(layout);
-        unsafe {
-            self.add_free_region(
-                NonNull::new(ptr::slice_from_raw_parts_mut(ptr, layout.size())).unwrap(),
+    /// Panics if the part of `alloc` that was in the body of the free region
+    /// at `region_start` no longer holds the poison byte, which means that
+    /// freed memory was written to. Only checked with debug assertions.
+    ///
+    /// This function is unsafe because the caller must guarantee that `alloc`
+    /// was just taken from the free region at `region_start`.
+    unsafe fn check_poison(&self, alloc: NonNull<[u8]>, region_start: usize) {
+        let Some(byte) = self.poison.filter(|_| cfg!(debug_assertions)) else {
+            return;
+        };
+        let start = alloc.as_mut_ptr();
+        let end = start.addr() + alloc.len();
+        let body = Ord::max(start.addr(), region_start + mem::size_of::<Node>());
+        if body >= end {
+            return;
+        }
+        let body = unsafe { &*ptr::slice_from_raw_parts(start.with_addr(body), end - body) };
+        alloc_assert(
+            body.iter().all(|&b| b == byte),
+            "use after free: freed memory was written to",
+        );
+    }
+
+    /// Frees the allocation at `ptr` and returns the `(start, size)` of the
+    /// free region it ends up in. When coalescing, this includes any adjacent
+    /// free regions it was merged with.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that `ptr` is
+    /// a live allocation from this allocator made with `layout`.
+    pub unsafe fn dealloc_returning(&mut self, ptr: *mut u8, layout: Layout) -> (usize, usize) {
+        if layout.size() == 0 {
+            return (ptr.addr(), 0);
+        }
+        if let (Some(start), Some(end)) = (self.heap_start(), self.heap_end()) {
+            debug_assert!(
+                (start..end).contains(&ptr.addr()),
+                "freeing {ptr:p} outside of the heap"
             );
         }
+        unsafe { self.free_block(ptr, layout) }
     }
-}
 
-// node: Node is the header of a memory region of size node.size >=
-// size_of::<Node>() bytes, except for the dummy node at the start of
-// Allocator
-struct Node {
-    size: usize,
-    next: Option<NonNull<Node>>,
-}
+    /// The part of `dealloc_returning` after the checks on `ptr`, shared with
+    /// `dealloc_unchecked`.
+    ///
+    /// This function is unsafe because the caller must guarantee that `ptr` is
+    /// a live allocation from this allocator made with `layout`, which is not
+    /// zero-sized.
+    unsafe fn free_block(&mut self, ptr: *mut u8, layout: Layout) -> (usize, usize) {
+        let mut size = unsafe { unwrap_invariant(self.block_layout(layout)) }.size();
+        self.remove_guard(ptr, size);
+        size += self.detach_tail(ptr.addr() + size);
+        self.allocations -= 1;
+        unsafe { self.poison_freed(ptr, size) };
+        let region =
+            unsafe { unwrap_invariant(NonNull::new(ptr::slice_from_raw_parts_mut(ptr, size))) };
+        if !self.coalesce {
+            unsafe { self.push_region(region) };
+            self.recent = Some(ptr.addr());
+            return (ptr.addr(), size);
+        }
 
-impl Node {
-    fn end(this: *mut Node) -> *mut u8 {
-        this.cast::<u8>()
-            .map_addr(|addr| addr + unsafe { (*this).size })
+        let start = ptr.addr();
+        // never merge free regions across the boundary between two memory
+        // regions, even if they happen to be adjacent
+        if let Some(prev) = self
+            .find_prev(start + size)
+            .filter(|_| !self.is_region_start(start + size))
+        {
+            unsafe {
+                let next = unwrap_invariant((*prev).next).as_ptr();
+                (*prev).next = (*next).next.take();
+                size += (*next).size;
+                self.poison_merged(next);
+            }
+        }
+        if let Some(node) = self
+            .nodes()
+            .find(|node| Node::end(node.as_ptr()).addr() == start)
+            .filter(|_| !self.is_region_start(start))
+        {
+            let node = node.as_ptr();
+            unsafe {
+                (*node).size += size;
+                self.recent = Some(node.addr());
+                return (node.addr(), (*node).size);
+            }
+        }
+        unsafe {
+            self.push_region(unwrap_invariant(NonNull::new(
+                ptr::slice_from_raw_parts_mut(ptr, size),
+            )));
+        }
+        self.recent = Some(start);
+        (start, size)
     }
-    fn alloc_from_region(this: *mut Self, layout: Layout) -> Option<NonNull<[u8]>> {
-        let alloc_start = this.cast::<u8>().try_align_up(layout.align())?;
-        let alloc_end = alloc_start.with_addr(alloc_start.addr().checked_add(layout.size())?);
 
-        if alloc_end > Node::end(this) {
+    /// Extends the memory region ending with the allocation at `ptr` by
+    /// `extra`, which must start right at its end, and grows the allocation
+    /// in place to take it all up. Returns the grown allocation, or `None` if
+    /// the allocation does not end its region, `extra` is not contiguous with
+    /// it, or the grown allocation would be too big.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that `ptr` is
+    /// a live allocation from this allocator made with `old_layout`, and that
+    /// `extra` is valid and unused.
+    pub unsafe fn absorb_following(
+        &mut self,
+        ptr: *mut u8,
+        old_layout: Layout,
+        extra: NonNull<[u8]>,
+    ) -> Option<NonNull<[u8]>> {
+        let old_size = unsafe { unwrap_invariant(Allocator::adjust(old_layout)) }.size();
+        let end = ptr.addr() + old_size;
+        let slot = self.region_slot_of(ptr.addr())?;
+        let region = self.regions[slot]?;
+        if self.guard_bytes > 0
+            || extra.addr().get() != end
+            || region.addr().get() + region.len() != end
+        {
             return None;
         }
-
-        let excess_size = Node::end(this).addr() - alloc_end.addr();
-        if 0 < excess_size && excess_size < mem::size_of::<Node>() {
+        let new_size = old_size.checked_add(extra.len())?;
+        if matches!(self.max_alloc, Some(max) if new_size > max) {
             return None;
         }
-
-        NonNull::new(ptr::slice_from_raw_parts_mut(alloc_start, layout.size()))
+        // the allocation has to stay freeable with its new size
+        let new_layout = Layout::from_size_align(new_size, old_layout.align()).ok()?;
+        if Allocator::adjust(new_layout)?.size() != new_size {
+            return None;
+        }
+        self.regions[slot] = NonNull::new(ptr::slice_from_raw_parts_mut(
+            region.as_mut_ptr(),
+            region.len() + extra.len(),
+        ));
+        self.mark_used(ptr.addr(), ptr.addr() + new_size);
+        NonNull::new(ptr::slice_from_raw_parts_mut(ptr, new_size))
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use core::{
-        alloc::Layout,
-        cell::SyncUnsafeCell,
-        mem,
-        ptr::{addr_of_mut, slice_from_raw_parts_mut, NonNull},
-    };
 
-    use static_assertions::const_assert_eq;
+    /// Like [`alloc`](super::Allocator::alloc), but in profiling builds tags
+    /// the allocation with `tag`, for instance a call site id, which
+    /// [`dump`](Allocator::dump) reports for as long as it is live. Otherwise
+    /// the tag is ignored.
+    ///
+    /// The allocation must be freed with
+    /// [`dealloc_tagged`](Allocator::dealloc_tagged).
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe for the same reasons as `alloc`.
+    pub unsafe fn alloc_tagged(&mut self, layout: Layout, tag: u32) -> Option<NonNull<[u8]>> {
+        #[cfg(feature = "profiling")]
+        {
+            let mut tagged = mem::replace(&mut self.tagged, HeaderList::new());
+            let ptr = unsafe { tagged.alloc(self, layout, tag) };
+            self.tagged = tagged;
+            ptr
+        }
+        #[cfg(not(feature = "profiling"))]
+        {
+            let _ = tag;
+            unsafe { super::Allocator::alloc(self, layout) }
+        }
+    }
 
-    use super::{Allocator, Node};
-    use crate::Allocator as _;
+    /// Frees an allocation made with [`alloc_tagged`](Allocator::alloc_tagged).
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that `ptr` is
+    /// a live allocation made by `alloc_tagged` on this allocator with
+    /// `layout`.
+    pub unsafe fn dealloc_tagged(&mut self, ptr: *mut u8, layout: Layout) {
+        #[cfg(feature = "profiling")]
+        {
+            let mut tagged = mem::replace(&mut self.tagged, HeaderList::new());
+            unsafe { tagged.dealloc(self, ptr, layout) };
+            self.tagged = tagged;
+        }
+        #[cfg(not(feature = "profiling"))]
+        unsafe {
+            super::Allocator::dealloc(self, ptr, layout)
+        }
+    }
 
-    #[repr(align(8))]
-    struct MemPool<const N: usize>([u8; N]);
-    const_assert_eq!(mem::align_of::<MemPool<1>>(), mem::align_of::<Node>());
+    /// Like [`realloc`](super::Allocator::realloc) for an allocation made
+    /// with [`alloc_tagged`](Allocator::alloc_tagged). In profiling builds
+    /// the allocation keeps its tag, whether it is resized in place or moved.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe for the same reasons as `dealloc_tagged`.
+    pub unsafe fn realloc_tagged(
+        &mut self,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> Option<NonNull<[u8]>> {
+        #[cfg(feature = "profiling")]
+        {
+            let mut tagged = mem::replace(&mut self.tagged, HeaderList::new());
+            let new = unsafe { tagged.realloc(self, ptr, layout, new_size) };
+            self.tagged = tagged;
+            new
+        }
+        #[cfg(not(feature = "profiling"))]
+        unsafe {
+            super::Allocator::realloc(self, ptr, layout, new_size)
+        }
+    }
 
-    #[test]
-    fn test() {
-        const HEAP_SIZE: usize = 1 << 12;
-        static HEAP1: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
-            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
-        static HEAP2: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
-            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
-        let mut alloc = Allocator::new();
+    /// Like [`alloc`](super::Allocator::alloc), but in debug builds records
+    /// where it was called from, which
+    /// [`report_leaks`](Allocator::report_leaks) reports for as long as the
+    /// allocation is live.
+    ///
+    /// The allocation must be freed with
+    /// [`dealloc_traced`](Allocator::dealloc_traced).
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe for the same reasons as `alloc`.
+    #[track_caller]
+    pub unsafe fn alloc_traced(&mut self, layout: Layout) -> Option<NonNull<[u8]>> {
+        #[cfg(debug_assertions)]
+        {
+            let mut traced = mem::replace(&mut self.traced, HeaderList::new());
+            let ptr = unsafe { traced.alloc(self, layout, Location::caller()) };
+            self.traced = traced;
+            ptr
+        }
+        #[cfg(not(debug_assertions))]
         unsafe {
-            alloc.add_free_region(
-                NonNull::new(slice_from_raw_parts_mut(
-                    addr_of_mut!((*HEAP1.get()).0).cast(),
-                    HEAP_SIZE,
-                ))
-                .unwrap(),
-            );
-            alloc.add_free_region(
-                NonNull::new(slice_from_raw_parts_mut(
-                    addr_of_mut!((*HEAP2.get()).0).cast(),
-                    HEAP_SIZE,
-                ))
-                .unwrap(),
-            );
+            super::Allocator::alloc(self, layout)
         }
-        let l1 = Layout::new::<[u8; HEAP_SIZE]>();
-        let l2 = Layout::new::<u64>();
-        let l3 = Layout::new::<u64>();
+    }
+
+    /// Frees an allocation made with [`alloc_traced`](Allocator::alloc_traced).
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that `ptr` is
+    /// a live allocation made by `alloc_traced` on this allocator with
+    /// `layout`.
+    pub unsafe fn dealloc_traced(&mut self, ptr: *mut u8, layout: Layout) {
+        #[cfg(debug_assertions)]
+        {
+            let mut traced = mem::replace(&mut self.traced, HeaderList::new());
+            unsafe { traced.dealloc(self, ptr, layout) };
+            self.traced = traced;
+        }
+        #[cfg(not(debug_assertions))]
         unsafe {
-            let p1 = alloc.alloc(l1).unwrap();
-            let p2 = alloc.alloc(l2).unwrap();
-            let p3 = alloc.alloc(l3).unwrap();
-            alloc.dealloc(p1.as_mut_ptr(), l1);
-            alloc.dealloc(p3.as_mut_ptr(), l2);
-            alloc.dealloc(p2.as_mut_ptr(), l2);
+            super::Allocator::dealloc(self, ptr, layout)
+        }
+    }
+
+    /// Writes the live allocations made with
+    /// [`alloc_traced`](Allocator::alloc_traced), most recent first, as their
+    /// address, size and where they were made. Release builds do not keep
+    /// track and write nothing.
+    pub fn report_leaks<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        #[cfg(debug_assertions)]
+        for (addr, size, location) in self.traced.iter() {
+            writeln!(w, "leaked {addr:#x} {size} at {location}")?;
+        }
+        #[cfg(not(debug_assertions))]
+        let _ = w;
+        Ok(())
+    }
+
+    /// Allocates a run of `n` contiguous, page-aligned pages, returning the
+    /// rest of the free region it is carved out of to the list. Fails if no
+    /// single free region can hold the whole run.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe for the same reasons as
+    /// [`alloc`](super::Allocator::alloc).
+    pub unsafe fn alloc_contiguous_pages(&mut self, n: usize) -> Option<NonNull<[u8]>> {
+        if n == 0 {
+            return None;
+        }
+        let layout = Layout::from_size_align(n.checked_mul(PAGE_SIZE)?, PAGE_SIZE).ok()?;
+        unsafe { super::Allocator::alloc(self, layout) }
+    }
+
+    /// Like [`alloc`](super::Allocator::alloc), but the allocation must end
+    /// at or below `max_addr`, such as for devices that can only address
+    /// part of memory. A free region straddling `max_addr` is used for its
+    /// part below it.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe for the same reasons as
+    /// [`alloc`](super::Allocator::alloc).
+    pub unsafe fn alloc_within(
+        &mut self,
+        layout: Layout,
+        max_addr: usize,
+    ) -> Option<NonNull<[u8]>> {
+        let placement = Placement {
+            max_addr,
+            ..Placement::ANYWHERE
+        };
+        self.try_alloc_placed(layout, placement)
+            .ok()
+            .map(|(alloc, _)| alloc)
+    }
+
+    /// Like [`alloc`](super::Allocator::alloc), but the allocation does not
+    /// cross a multiple of `boundary`, which must be a power of two, such as
+    /// for DMA buffers that must stay within one 64 KiB window. If needed,
+    /// the allocation is moved up to the start of the next window, and the
+    /// bytes skipped over are left free.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe for the same reasons as
+    /// [`alloc`](super::Allocator::alloc).
+    pub unsafe fn alloc_no_cross(
+        &mut self,
+        layout: Layout,
+        boundary: usize,
+    ) -> Option<NonNull<[u8]>> {
+        assert!(
+            boundary.is_power_of_two(),
+            "boundary must be a power of two"
+        );
+        let placement = Placement {
+            boundary: Some(boundary),
+            ..Placement::ANYWHERE
+        };
+        self.try_alloc_placed(layout, placement)
+            .ok()
+            .map(|(alloc, _)| alloc)
+    }
+
+    /// Like [`alloc`](super::Allocator::alloc), but if there is no room,
+    /// `grow` is asked once for a new memory region of at least the given
+    /// number of bytes, which is then added to the allocator for another
+    /// try. The number accounts for any alignment the new region might lack.
+    /// Any other reason the allocation fails is returned without growing. If
+    /// the new region cannot be added, it is handed back with the reason.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because, besides the reasons for
+    /// [`alloc`](super::Allocator::alloc), the caller must guarantee that the
+    /// region returned by `grow` is valid and unused.
+    pub unsafe fn alloc_or_grow(
+        &mut self,
+        layout: Layout,
+        grow: impl FnOnce(usize) -> Option<NonNull<[u8]>>,
+    ) -> Result<NonNull<[u8]>, GrowError> {
+        match unsafe { super::Allocator::try_alloc(self, layout) } {
+            Err(AllocError::OutOfMemory) => {}
+            result => return result.map_err(GrowError::Alloc),
+        }
+        let adjusted = self
+            .block_layout(layout)
+            .ok_or(GrowError::Alloc(AllocError::LayoutOverflow))?;
+        let needed = adjusted
+            .size()
+            .checked_add(adjusted.align() - mem::align_of::<Node>())
+            .ok_or(GrowError::Alloc(AllocError::LayoutOverflow))?;
+        let region = grow(needed).ok_or(GrowError::Alloc(AllocError::OutOfMemory))?;
+        if let Err(err) = unsafe { self.try_add_free_region(region) } {
+            return Err(GrowError::Rejected(region, err));
+        }
+        unsafe { super::Allocator::try_alloc(self, layout) }.map_err(GrowError::Alloc)
+    }
+
+    /// Carves the free region starting at `start` into two adjacent
+    /// allocations of `first_size` and `second_size` bytes, in that order,
+    /// leaving the rest of the region free. Returns `None` if either size is
+    /// zero, there is no free region at `start`, the two do not fit in it, or
+    /// they are over the limits of the allocator. The allocations are freed
+    /// with layouts of their sizes aligned to `usize`.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe for the same reasons as
+    /// [`alloc`](super::Allocator::alloc).
+    pub unsafe fn split_free_block(
+        &mut self,
+        start: usize,
+        first_size: usize,
+        second_size: usize,
+    ) -> Option<SplitBlock> {
+        // a zero-sized piece would still take up a whole node, which freeing
+        // it as a zero-sized allocation would never give back
+        if matches!(self.max_allocations, Some(max) if self.allocations + 2 > max)
+            || self.guard_bytes > 0
+            || first_size == 0
+            || second_size == 0
+        {
+            return None;
+        }
+        self.check_limits(first_size).ok()?;
+        self.check_limits(second_size).ok()?;
+        let [first, second] = [first_size, second_size].map(|size| {
+            Layout::from_size_align(size, mem::align_of::<Node>())
+                .ok()
+                .and_then(Allocator::adjust)
+        });
+        let (first, second) = (first?.size(), second?.size());
+        let prev = self.find_prev(start)?;
+        let region = unsafe { unwrap_invariant((*prev).next) }.as_ptr();
+        let excess_size = unsafe { (*region).size }.checked_sub(first.checked_add(second)?)?;
+        // the rest takes the place of the region, so the number of free
+        // regions does not grow past `max_free_blocks`
+        let min_excess = Ord::max(self.min_useful_remainder, mem::size_of::<Node>());
+        if (0 < excess_size && excess_size < min_excess) || self.no_split {
+            return None;
+        }
+        unsafe { (*prev).next = (*region).next.take() };
+        // derive both allocations and the rest from the region, which has
+        // provenance for all of it
+        let first_start = region.cast::<u8>();
+        let second_start = first_start.map_addr(|addr| addr + first);
+        if excess_size > 0 {
+            unsafe {
+                self.push_region(unwrap_invariant(NonNull::new(
+                    ptr::slice_from_raw_parts_mut(
+                        second_start.map_addr(|addr| addr + second),
+                        excess_size,
+                    ),
+                )));
+            }
+        }
+        self.allocations += 2;
+        unsafe {
+            self.check_poison(
+                unwrap_invariant(NonNull::new(ptr::slice_from_raw_parts_mut(
+                    first_start,
+                    first + second,
+                ))),
+                first_start.addr(),
+            );
+        }
+        self.mark_used(first_start.addr(), second_start.addr() + second);
+        let [first, second] =
+            [(first_start, first_size), (second_start, second_size)].map(|(start, size)| unsafe {
+                unwrap_invariant(NonNull::new(ptr::slice_from_raw_parts_mut(start, size)))
+            });
+        Some((first, second))
+    }
+
+    /// Like [`alloc`](super::Allocator::alloc), but also returns how many
+    /// free blocks were split off the block the allocation came from: 0 if
+    /// it took up the whole block, 1 if the rest was left free and 2 if the
+    /// alignment gap in front of it was as well. The number of free blocks
+    /// goes down by one less than that.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe for the same reasons as
+    /// [`alloc`](super::Allocator::alloc).
+    pub unsafe fn alloc_reporting(&mut self, layout: Layout) -> Option<(NonNull<[u8]>, i32)> {
+        self.try_alloc_placed(layout, Placement::ANYWHERE).ok()
+    }
+
+    /// Like [`alloc`](super::Allocator::alloc), but for a layout that already
+    /// went through [`adjust`](Allocator::adjust), so that allocating the
+    /// same type over and over does not adjust its layout every time. The
+    /// allocation can be freed with either layout. A size limit set with
+    /// [`max_alloc`](Builder::max_alloc) applies to the adjusted size.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that
+    /// `adjusted` was returned by `adjust`, on top of the reasons for `alloc`.
+    pub unsafe fn alloc_adjusted(&mut self, adjusted: Layout) -> Option<NonNull<[u8]>> {
+        debug_assert_eq!(
+            Allocator::adjust(adjusted),
+            Some(adjusted),
+            "layout was not adjusted"
+        );
+        self.check_limits(adjusted.size()).ok()?;
+        self.try_alloc_block(adjusted, Placement::ANYWHERE)
+            .map(|(alloc, _)| alloc)
+            .ok()
+    }
+
+    /// Returns why an allocation of `size` bytes is not allowed, if it is
+    /// over the limits of this allocator.
+    fn check_limits(&self, size: usize) -> Result<(), AllocError> {
+        if matches!(self.max_alloc, Some(max) if size > max) {
+            return Err(AllocError::ExceedsLimit);
+        }
+        if matches!(self.max_allocations, Some(max) if self.allocations >= max) {
+            return Err(AllocError::TooManyAllocations);
+        }
+        Ok(())
+    }
+
+    /// Allocates from the free list like `try_alloc`, but only where
+    /// `placement` allows, returning how many free blocks were split off.
+    fn try_alloc_placed(
+        &mut self,
+        layout: Layout,
+        placement: Placement,
+    ) -> Result<(NonNull<[u8]>, i32), AllocError> {
+        if layout.size() == 0 {
+            return Ok((crate::dangling(layout), 0));
+        }
+        self.check_limits(layout.size())?;
+        let adjusted = Allocator::adjust(layout).ok_or(AllocError::LayoutOverflow)?;
+        self.try_alloc_block(adjusted, placement)
+    }
+
+    /// The part of `try_alloc_placed` after the layout is adjusted and
+    /// checked against the limits.
+    fn try_alloc_block(
+        &mut self,
+        adjusted: Layout,
+        placement: Placement,
+    ) -> Result<(NonNull<[u8]>, i32), AllocError> {
+        let layout = self
+            .with_guard(adjusted)
+            .ok_or(AllocError::LayoutOverflow)?;
+        let (region, mut alloc) = self.find_region(layout, placement)?;
+        unsafe { self.check_poison(alloc, region.addr().get()) };
+        self.mark_used(alloc.addr().get(), alloc.addr().get() + alloc.len());
+        // derive the excess regions from the region the allocation came from,
+        // which has provenance for all of it
+        let region_start = region.as_ptr().cast::<u8>();
+        let mut split_off = 0;
+        let region_end = Node::end(region.as_ptr());
+        let alloc_end = region_end.with_addr(alloc.as_mut_ptr().addr() + alloc.len());
+        let excess_size = region_end.addr() - alloc_end.addr();
+        if excess_size > 0 {
+            split_off += 1;
+            unsafe {
+                invariant(excess_size >= mem::size_of::<Node>());
+                self.push_region(unwrap_invariant(NonNull::new(
+                    ptr::slice_from_raw_parts_mut(alloc_end, excess_size),
+                )));
+            }
+        }
+        let lead_size = alloc.as_mut_ptr().addr() - region_start.addr();
+        if lead_size > 0 {
+            split_off += 1;
+            unsafe {
+                invariant(lead_size >= mem::size_of::<Node>());
+                self.push_region(unwrap_invariant(NonNull::new(
+                    ptr::slice_from_raw_parts_mut(region_start, lead_size),
+                )));
+            }
+        }
+        let tail_size = alloc.len() - layout.size();
+        if tail_size > 0 {
+            unsafe {
+                self.attach_tail(alloc_end.map_addr(|addr| addr - tail_size), tail_size);
+                alloc = unwrap_invariant(NonNull::new(ptr::slice_from_raw_parts_mut(
+                    alloc.as_mut_ptr(),
+                    layout.size(),
+                )));
+            }
+        }
+        self.allocations += 1;
+        Ok((unsafe { self.place_guard(alloc) }, split_off))
+    }
+
+    /// Grows the allocation at `ptr` in place by taking up the free region
+    /// right after it, which must not be the start of another added memory
+    /// region. The new size is held to
+    /// [`max_alloc`](Builder::max_alloc) like a new allocation.
+    ///
+    /// This function is unsafe because the caller must guarantee that `ptr` is
+    /// a live allocation from this allocator made with `layout`.
+    unsafe fn grow_into_next(&mut self, ptr: *mut u8, layout: Layout, new_size: usize) -> bool {
+        if matches!(self.max_alloc, Some(max) if new_size > max) {
+            return false;
+        }
+        let Ok(new_layout) = Layout::from_size_align(new_size, layout.align()) else {
+            return false;
+        };
+        let old_size = unsafe { unwrap_invariant(Allocator::adjust(layout)) }.size();
+        let Some(new_size) = Allocator::adjust(new_layout).map(|layout| layout.size()) else {
+            return false;
+        };
+        if new_size <= old_size {
+            return new_size == old_size;
+        }
+        if self.is_region_start(ptr.addr() + old_size) {
+            return false;
+        }
+        let Some(prev) = self.find_prev(ptr.addr() + old_size) else {
+            return false;
+        };
+        let next = unsafe { unwrap_invariant((*prev).next) };
+        let next_end = Node::end(next.as_ptr());
+        let min_excess = Ord::max(self.min_useful_remainder, mem::size_of::<Node>());
+        let excess_size = match next_end.addr().checked_sub(ptr.addr() + new_size) {
+            Some(0) => 0,
+            Some(excess_size) if excess_size >= min_excess && !self.no_split => excess_size,
+            _ => return false,
+        };
+        self.mark_used(ptr.addr(), ptr.addr() + new_size);
+        unsafe {
+            (*prev).next = (*next.as_ptr()).next.take();
+            if excess_size > 0 {
+                // SAFETY: next has provenance for the whole free region being split
+                self.push_region(
+                    NonNull::new(ptr::slice_from_raw_parts_mut(
+                        next_end.with_addr(next_end.addr() - excess_size),
+                        excess_size,
+                    ))
+                    .unwrap(),
+                );
+            }
+        }
+        true
+    }
+
+    /// Merges all adjacent free regions, and returns how much that did.
+    pub fn merge_all(&mut self) -> DefragStats {
+        self.defrag_cursor = 0;
+        self.defragment_incremental(usize::MAX)
+    }
+
+    /// Walks the free regions in address order from where the previous call
+    /// stopped, merging each with the free region right after it, if any.
+    /// At most `budget` regions are examined per call.
+    ///
+    /// Returns how much the call did, including whether there is more work
+    /// left, in which case a later call will carry on from here.
+    pub fn defragment_incremental(&mut self, budget: usize) -> DefragStats {
+        let mut stats = DefragStats {
+            merges: 0,
+            before_largest: self.largest_free_block(),
+            after_largest: 0,
+            before_blocks: self.free_block_count(),
+            after_blocks: 0,
+            more: true,
+        };
+        for _ in 0..budget {
+            let cursor = self.defrag_cursor;
+            let Some(node) = self
+                .nodes()
+                .filter(|node| node.addr().get() >= cursor)
+                .min_by_key(|node| node.addr())
+            else {
+                self.defrag_cursor = 0;
+                stats.more = false;
+                break;
+            };
+            let node = node.as_ptr();
+            let end = Node::end(node).addr();
+            match self.find_prev(end).filter(|_| !self.is_region_start(end)) {
+                Some(prev) => unsafe {
+                    let next = unwrap_invariant((*prev).next).as_ptr();
+                    (*prev).next = (*next).next.take();
+                    (*node).size += (*next).size;
+                    self.poison_merged(next);
+                    stats.merges += 1;
+                },
+                None => self.defrag_cursor = end,
+            }
+        }
+        stats.after_largest = self.largest_free_block();
+        stats.after_blocks = self.free_block_count();
+        stats
+    }
+
+    /// Returns the `(start, size)` of the lowest-addressed free region that
+    /// starts at or above `addr`.
+    pub fn first_free_above(&self, addr: usize) -> Option<(usize, usize)> {
+        self.nodes()
+            .map(|node| (node.addr().get(), unsafe { (*node.as_ptr()).size }))
+            .filter(|&(start, _)| start >= addr)
+            .min_by_key(|&(start, _)| start)
+    }
+
+    /// Returns whether the memory at `ptr` is free or allocated, or `None`
+    /// if it is not in any memory region added to the allocator. Without
+    /// block headers, memory that is not in a free region counts as
+    /// allocated, including the padding of allocations.
+    pub fn status_of(&self, ptr: *mut u8) -> Option<BlockStatus> {
+        let addr = ptr.addr();
+        self.region_slot_of(addr)?;
+        let free = self.nodes().any(|node| {
+            let start = node.addr().get();
+            (start..start + unsafe { (*node.as_ptr()).size }).contains(&addr)
+        });
+        Some(if free {
+            BlockStatus::Free
+        } else {
+            BlockStatus::Allocated
+        })
+    }
+
+    /// Writes the free regions, in list order, and a summary of them to `w`.
+    /// Profiling builds then write the live tagged allocations, most recent
+    /// first, as their address, size and tag.
+    pub fn dump<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        let (mut count, mut free, mut largest) = (0, 0, 0);
+        for node in self.nodes() {
+            let size = unsafe { (*node.as_ptr()).size };
+            writeln!(w, "{:#x} {}", node.addr(), size)?;
+            count += 1;
+            free += size;
+            largest = Ord::max(largest, size);
+        }
+        writeln!(
+            w,
+            "{count} free regions, {free} bytes free, largest {largest} bytes"
+        )?;
+        #[cfg(feature = "profiling")]
+        for (addr, size, tag) in self.tagged.iter() {
+            writeln!(w, "tagged {addr:#x} {size} {tag}")?;
+        }
+        Ok(())
+    }
+
+    /// Keeps only the free regions for which `f(start, size)` returns
+    /// `true` and returns how many were removed. The removed regions are
+    /// counted as used from then on, and the caller can hand them to another
+    /// allocator.
+    pub fn retain_free_blocks(&mut self, mut f: impl FnMut(usize, usize) -> bool) -> usize {
+        let mut removed = 0;
+        let mut curr = addr_of_mut!(self.head);
+        while let Some(region) = unsafe { (*curr).next } {
+            let region = region.as_ptr();
+            if unsafe { f(region.addr(), (*region).size) } {
+                curr = region;
+            } else {
+                unsafe { (*curr).next = (*region).next.take() };
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Returns how many bytes more than `layout.size()` an allocation for
+    /// `layout` takes up. The size is rounded up to a multiple of the
+    /// alignment, so a small type with a large alignment pays for most of
+    /// the alignment: 8 bytes aligned to 64 take up 64 bytes, 56 of them
+    /// padding.
+    pub fn overhead_for(layout: Layout) -> usize {
+        Allocator::adjust(layout)
+            .unwrap_or_else(|| alloc_fail("layout too large"))
+            .size()
+            - layout.size()
+    }
+
+    /// Returns the total size of the free regions.
+    pub fn free_bytes(&self) -> usize {
+        self.nodes()
+            .map(|node| unsafe { (*node.as_ptr()).size })
+            .sum()
+    }
+
+    /// Returns the number of bytes of the added memory regions that are not
+    /// free, including the padding of allocations.
+    pub fn used_bytes(&self) -> usize {
+        let total: usize = self.added_regions().map(|region| region.len()).sum();
+        total - self.free_bytes() - self.guard_total()
+    }
+
+    /// Returns the total size of the guards after live allocations.
+    pub fn guard_total(&self) -> usize {
+        let guard_size = self.guard_size().unwrap_or(0);
+        iter::successors(self.guards, |guard| unsafe { (*guard.as_ptr()).next }).count()
+            * guard_size
+    }
+
+    /// Returns the number of free regions.
+    pub fn free_block_count(&self) -> usize {
+        self.nodes().count()
+    }
+
+    /// Returns an FNV-1a hash of the start and size of each free region in
+    /// list order, which only changes when the free list does. Comparing it
+    /// with an earlier value tells whether the free list was disturbed, for
+    /// instance across a suspend and resume. The list is walked as is, so a
+    /// list that may have been corrupted into a loop should pass
+    /// [`verify`](Allocator::verify) first.
+    pub fn free_list_checksum(&self) -> u64 {
+        const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0100_0000_01b3;
+        self.nodes()
+            .flat_map(|node| {
+                let (start, size) = (node.addr().get(), unsafe { (*node.as_ptr()).size });
+                start.to_ne_bytes().into_iter().chain(size.to_ne_bytes())
+            })
+            .fold(OFFSET_BASIS, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+            })
+    }
+
+    /// Returns the size of the largest free region, or 0 if there are none.
+    pub fn largest_free_block(&self) -> usize {
+        self.nodes()
+            .map(|node| unsafe { (*node.as_ptr()).size })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns whether there are no outstanding allocations.
+    pub fn is_empty(&self) -> bool {
+        self.used_bytes() == 0
+    }
+
+    /// Returns whether no allocation can succeed, because there is no free
+    /// memory left.
+    pub fn is_full(&self) -> bool {
+        self.largest_free_block() == 0
+    }
+
+    /// Returns the number of memory regions added to the allocator.
+    pub fn region_count(&self) -> usize {
+        self.region_count
+    }
+
+    /// Returns the memory regions added to the allocator, in the order of
+    /// their ids.
+    fn added_regions(&self) -> impl Iterator<Item = NonNull<[u8]>> + '_ {
+        self.regions.iter().flatten().copied()
+    }
+
+    /// Returns whether one of the added memory regions starts at `addr`.
+    fn is_region_start(&self, addr: usize) -> bool {
+        self.added_regions()
+            .any(|region| region.addr().get() == addr)
+    }
+
+    /// Rebuilds the free list as one free region for each memory region added
+    /// to the allocator, which must have no outstanding allocations. This is
+    /// cheaper than merging the free regions when everything has been freed.
+    pub fn coalesce_to_regions(&mut self) {
+        assert!(self.is_empty(), "allocations outstanding");
+        self.head.next = None;
+        self.defrag_cursor = 0;
+        for i in 0..MAX_REGIONS {
+            if let Some(region) = self.regions[i] {
+                // the nodes of the free regions are left behind
+                self.pristine[i] = region.addr().get() + region.len();
+                unsafe {
+                    self.poison_freed(region.as_mut_ptr(), region.len());
+                    self.push_region(region);
+                }
+            }
+        }
+    }
+
+    /// Turns an allocator with a single memory region and no outstanding
+    /// allocations into a bump allocator over that region, for a phase of
+    /// allocations that need not be freed individually. Returns `None` if the
+    /// allocator is in use or has other than one region. Nothing ties the
+    /// region to a lifetime, so the bump allocator is made by
+    /// [`bump::Allocator::new`] and does not expose its bytes.
+    #[cfg(feature = "bump")]
+    pub fn into_bump(mut self) -> Option<bump::Allocator<'static>> {
+        if self.region_count != 1 || !self.is_empty() {
+            return None;
+        }
+        self.coalesce_to_regions();
+        let region = self.added_regions().next()?;
+        Some(bump::Allocator::new(region))
+    }
+
+    /// Moves the live memory of an allocator with a single memory region down
+    /// to the start of the region, closing the gaps between allocations, and
+    /// returns a bump allocator over the free tail that is left, made by
+    /// [`bump::Allocator::new`] like the one from
+    /// [`into_bump`](Allocator::into_bump). The allocator keeps the compacted
+    /// part of the region, with no free regions, and freeing a moved
+    /// allocation at its new address gives it back to the allocator.
+    ///
+    /// Each run of live memory between two free regions is passed to
+    /// `relocate` as its old start, new start and size, in address order, for
+    /// it to move. The new place can overlap the old one.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that
+    /// `relocate` moves the memory like [`ptr::copy`] and updates whatever
+    /// points into it, and that no live allocation was made with an
+    /// alignment larger than that of `usize`, which is all that moved memory
+    /// keeps. Panics if there are live tagged or traced allocations, whose
+    /// headers the allocator keeps track of.
+    #[cfg(feature = "bump")]
+    pub unsafe fn compact<F: FnMut(*mut u8, *mut u8, usize)>(
+        &mut self,
+        mut relocate: F,
+    ) -> bump::Allocator<'static> {
+        alloc_assert(self.region_count == 1, "compacting needs a single region");
+        alloc_assert(self.guard_bytes == 0, "compacting would move the guards");
+        alloc_assert(
+            self.tails.is_null(),
+            "compacting would move the attached tails",
+        );
+        // the headers of tagged and traced allocations link to each other
+        #[cfg(feature = "profiling")]
+        alloc_assert(
+            self.tagged.head.is_none(),
+            "compacting would move the tagged allocations",
+        );
+        #[cfg(debug_assertions)]
+        alloc_assert(
+            self.traced.head.is_none(),
+            "compacting would move the traced allocations",
+        );
+        let slot = unsafe { unwrap_invariant(self.regions.iter().position(Option::is_some)) };
+        let region = unsafe { unwrap_invariant(self.regions[slot]) };
+        let base = region.as_mut_ptr();
+        let end = base.addr() + region.len();
+        let (mut cursor, mut dest) = (base.addr(), base.addr());
+        loop {
+            // unlink the next free region before any memory below it is
+            // moved over the nodes in front of it
+            let free = self.first_free_above(cursor);
+            if let Some((start, _)) = free {
+                unsafe {
+                    let prev = unwrap_invariant(self.find_prev(start));
+                    let node = unwrap_invariant((*prev).next).as_ptr();
+                    (*prev).next = (*node).next.take();
+                }
+            }
+            let run_end = free.map_or(end, |(start, _)| start);
+            if run_end > cursor && dest != cursor {
+                relocate(
+                    base.with_addr(cursor),
+                    base.with_addr(dest),
+                    run_end - cursor,
+                );
+            }
+            dest += run_end - cursor;
+            match free {
+                Some((start, size)) => cursor = start + size,
+                None => break,
+            }
+        }
+        self.regions[slot] = NonNull::new(ptr::slice_from_raw_parts_mut(base, dest - base.addr()));
+        self.pristine[slot] = dest;
+        self.defrag_cursor = 0;
+        self.recent = None;
+        let tail = ptr::slice_from_raw_parts_mut(base.with_addr(dest), end - dest);
+        bump::Allocator::new(unsafe { unwrap_invariant(NonNull::new(tail)) })
+    }
+
+    /// Moves the memory regions of `other`, along with its free list, into
+    /// this allocator, leaving `other` empty. Used to consolidate heaps.
+    /// Panics if `other` has live allocations.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that `other`
+    /// has no outstanding allocations, as they would end up belonging to
+    /// this allocator.
+    pub unsafe fn absorb(&mut self, other: &mut Allocator) {
+        alloc_assert(
+            other.allocations == 0,
+            "absorbing an allocator with live allocations",
+        );
+        alloc_assert(
+            self.region_count + other.region_count <= MAX_REGIONS,
+            "too many regions",
+        );
+        for region in other.added_regions() {
+            let slot = unsafe { unwrap_invariant(self.free_region_slot()) };
+            self.regions[slot] = Some(region);
+            self.pristine[slot] = region.addr().get() + region.len();
+            self.region_count += 1;
+        }
+        while let Some(node) = other.head.next {
+            unsafe {
+                other.head.next = (*node.as_ptr()).next;
+                let region =
+                    ptr::slice_from_raw_parts_mut(node.as_ptr().cast(), (*node.as_ptr()).size);
+                // other may not poison, or poison with another byte
+                self.poison_freed(region.cast(), region.len());
+                self.push_region(unwrap_invariant(NonNull::new(region)));
+            }
+        }
+        for slot in 0..MAX_REGIONS {
+            if other.regions[slot].take().is_some() {
+                other.generations[slot] = other.generations[slot].wrapping_add(1);
+            }
+        }
+        other.region_count = 0;
+        other.defrag_cursor = 0;
+        other.recent = None;
+    }
+
+    /// Returns how much of the memory region `id` is free and used, or
+    /// `None` if there is no such region.
+    pub fn region_stats(&self, id: RegionId) -> Option<RegionStats> {
+        let region = self.region(id)?;
+        let (start, size) = (region.addr().get(), region.len());
+        let mut stats = RegionStats {
+            free_bytes: 0,
+            used_bytes: size,
+            free_blocks: 0,
+        };
+        for node in self.nodes() {
+            if (start..start + size).contains(&node.addr().get()) {
+                let node_size = unsafe { (*node.as_ptr()).size };
+                stats.free_bytes += node_size;
+                stats.used_bytes -= node_size;
+                stats.free_blocks += 1;
+            }
+        }
+        Some(stats)
+    }
+
+    /// Returns a description of each memory region added to the allocator,
+    /// in the order of their ids, with how much of it is free. The free list
+    /// is walked once, up front.
+    pub fn regions(&self) -> impl Iterator<Item = RegionInfo> + '_ {
+        let mut free = [(0, 0); MAX_REGIONS];
+        for node in self.nodes() {
+            let addr = node.addr().get();
+            let slot = self.regions.iter().position(|region| {
+                matches!(region, Some(region)
+                    if addr.wrapping_sub(region.addr().get()) < region.len())
+            });
+            if let Some(slot) = slot {
+                free[slot].0 += unsafe { (*node.as_ptr()).size };
+                free[slot].1 += 1;
+            }
+        }
+        self.regions.iter().zip(free).enumerate().filter_map(
+            |(slot, (region, (free_bytes, free_blocks)))| {
+                let region = (*region)?;
+                Some(RegionInfo {
+                    id: self.region_id(slot),
+                    start: region.addr().get(),
+                    size: region.len(),
+                    free_bytes,
+                    free_blocks,
+                })
+            },
+        )
+    }
+
+    /// Checks that the free list is well formed: it ends, and its nodes are
+    /// aligned, big enough to hold a node, within the address space and do
+    /// not overlap each other. With [guard bytes](Builder::guard_bytes), it
+    /// also checks that the guards after allocations hold their fill.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let step = |node: NonNull<Node>| {
+            if !node.as_ptr().is_aligned() {
+                return Err(VerifyError::Misaligned(node.addr().get()));
+            }
+            Ok(unsafe { (*node.as_ptr()).next })
+        };
+        // look for a cycle first, as the other checks walk the whole list.
+        // fast visits every node of an acyclic list, so it also checks
+        // alignment before anything else reads a node
+        let (mut slow, mut fast) = (self.head.next, self.head.next);
+        loop {
+            for _ in 0..2 {
+                fast = match fast {
+                    Some(node) => step(node)?,
+                    None => break,
+                };
+            }
+            let Some(node) = fast else {
+                break;
+            };
+            // slow trails fast along the same nodes, so it has not run out
+            slow = step(unsafe { unwrap_invariant(slow) })?;
+            if slow == Some(node) {
+                return Err(VerifyError::Cycle);
+            }
+        }
+
+        let range = |node: NonNull<Node>| {
+            let (start, size) = (node.addr().get(), unsafe { (*node.as_ptr()).size });
+            if size < mem::size_of::<Node>() {
+                return Err(VerifyError::TooSmall(start));
+            }
+            let end = start
+                .checked_add(size)
+                .ok_or(VerifyError::SizeOverflow(start))?;
+            Ok(start..end)
+        };
+        for (i, a) in self.nodes().enumerate() {
+            let a = range(a)?;
+            for b in self.nodes().skip(i + 1) {
+                let b = range(b)?;
+                if a.start < b.end && b.start < a.end {
+                    return Err(VerifyError::Overlap(a.start, b.start));
+                }
+            }
+        }
+
+        // there is a guard for each allocation, so a longer list is broken
+        let fill_size = self
+            .guard_size()
+            .map_or(0, |size| size.saturating_sub(mem::size_of::<Guard>()));
+        let mut guard = self.guards;
+        for _ in 0..=self.allocations {
+            let Some(link) = guard else {
+                return Ok(());
+            };
+            let start = link.addr().get().wrapping_sub(fill_size);
+            let end = link.addr().get().wrapping_add(mem::size_of::<Guard>() - 1);
+            let slot = self.region_slot_of(start);
+            if !link.as_ptr().is_aligned() || slot.is_none() || slot != self.region_slot_of(end) {
+                return Err(VerifyError::GuardOverwritten(start));
+            }
+            let fill = unsafe {
+                &*ptr::slice_from_raw_parts(link.as_ptr().cast::<u8>().with_addr(start), fill_size)
+            };
+            if fill.iter().any(|&b| b != GUARD_BYTE) {
+                return Err(VerifyError::GuardOverwritten(start));
+            }
+            guard = unsafe { (*link.as_ptr()).next };
+        }
+        match guard {
+            Some(link) => Err(VerifyError::GuardOverwritten(link.addr().get())),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns an iterator over the nodes of the free list.
+    fn nodes(&self) -> Nodes<'_> {
+        Nodes {
+            next: self.head.next,
+            _alloc: PhantomData,
+        }
+    }
+
+    /// Returns the list node whose successor is the free region starting at
+    /// `addr`, if there is one.
+    fn find_prev(&mut self, addr: usize) -> Option<*mut Node> {
+        let mut curr = addr_of_mut!(self.head);
+        while let Some(region) = unsafe { (*curr).next } {
+            if region.addr().get() == addr {
+                return Some(curr);
+            }
+            curr = region.as_ptr();
+        }
+        None
+    }
+
+    /// Adjust the given layout so that the resulting allocated memory
+    /// region is also capable of storing a `Node`. Returns `None` if the
+    /// adjusted layout would not be valid.
+    ///
+    /// Raising the alignment costs nothing: every block starts and ends at a
+    /// `Node`-aligned address, because a freed block has to hold a `Node`,
+    /// so the bytes a smaller alignment would save could not be handed out
+    /// to anything else.
+    ///
+    /// The result can be passed to [`alloc_adjusted`](Allocator::alloc_adjusted)
+    /// any number of times.
+    pub fn adjust(layout: Layout) -> Option<Layout> {
+        let layout = layout
+            .align_to(mem::align_of::<Node>())
+            .ok()?
+            .pad_to_align();
+        Layout::from_size_align(
+            Ord::max(layout.size(), mem::size_of::<Node>()),
+            layout.align(),
+        )
+        .ok()
+    }
+
+    /// Returns how many bytes the guard after each allocation takes up: the
+    /// fill, rounded up to keep blocks `Node`-aligned, and the link to the
+    /// next guard. Returns `None` if that overflows.
+    fn guard_size(&self) -> Option<usize> {
+        if self.guard_bytes == 0 {
+            return Some(0);
+        }
+        let align = mem::align_of::<Node>();
+        let fill = self.guard_bytes.checked_add(align - 1)? & !(align - 1);
+        fill.checked_add(mem::size_of::<Guard>())
+    }
+
+    /// Like [`adjust`](Allocator::adjust), but with room for the guard after
+    /// the allocation.
+    fn block_layout(&self, layout: Layout) -> Option<Layout> {
+        self.with_guard(Allocator::adjust(layout)?)
+    }
+
+    /// Returns the already adjusted `layout` with room for the guard after
+    /// the allocation.
+    fn with_guard(&self, layout: Layout) -> Option<Layout> {
+        Layout::from_size_align(
+            layout.size().checked_add(self.guard_size()?)?,
+            layout.align(),
+        )
+        .ok()
+    }
+
+    /// Fills in the guard at the end of the block `alloc` and returns the
+    /// part of the block in front of it.
+    ///
+    /// This function is unsafe because the caller must guarantee that `alloc`
+    /// was just allocated for a layout from `block_layout`.
+    unsafe fn place_guard(&mut self, alloc: NonNull<[u8]>) -> NonNull<[u8]> {
+        let guard_size = unsafe { unwrap_invariant(self.guard_size()) };
+        if guard_size == 0 {
+            return alloc;
+        }
+        let len = alloc.len() - guard_size;
+        let fill = alloc.as_mut_ptr().map_addr(|addr| addr + len);
+        let guard = fill
+            .map_addr(|addr| addr + guard_size - mem::size_of::<Guard>())
+            .cast::<Guard>();
+        unsafe {
+            fill.write_bytes(GUARD_BYTE, guard_size - mem::size_of::<Guard>());
+            guard.write(Guard { next: self.guards });
+        }
+        self.guards = NonNull::new(guard);
+        unsafe {
+            unwrap_invariant(NonNull::new(ptr::slice_from_raw_parts_mut(
+                alloc.as_mut_ptr(),
+                len,
+            )))
+        }
+    }
+
+    /// Unlinks the guard at the end of the block of `size` bytes at `ptr`.
+    /// Panics if there is none.
+    fn remove_guard(&mut self, ptr: *mut u8, size: usize) {
+        if self.guard_bytes == 0 {
+            return;
+        }
+        let addr = ptr.addr() + size - mem::size_of::<Guard>();
+        let mut link = addr_of_mut!(self.guards);
+        while let Some(guard) = unsafe { *link } {
+            if guard.addr().get() == addr {
+                unsafe { *link = (*guard.as_ptr()).next };
+                return;
+            }
+            link = unsafe { addr_of_mut!((*guard.as_ptr()).next) };
+        }
+        alloc_fail("freeing an allocation without a guard");
+    }
+
+    /// Records the `size` bytes at `tail` as left attached to the allocation
+    /// right in front of them.
+    ///
+    /// This function is unsafe because the caller must guarantee that the
+    /// bytes are part of a live block and not handed out to anyone.
+    unsafe fn attach_tail(&mut self, tail: *mut u8, size: usize) {
+        let record = tail.cast::<Tail>();
+        unsafe {
+            addr_of_mut!((*record).next).write(self.tails);
+            if size >= mem::size_of::<Tail>() {
+                addr_of_mut!((*record).size).write(size);
+            }
+        }
+        let single_word = usize::from(size < mem::size_of::<Tail>());
+        self.tails = record.map_addr(|addr| addr | single_word);
+        self.attached_bytes += u64::try_from(size).unwrap_or(u64::MAX);
+    }
+
+    /// Unlinks the tail at `addr`, if any, and returns its size, or 0 if
+    /// there is none.
+    fn detach_tail(&mut self, addr: usize) -> usize {
+        let mut link = addr_of_mut!(self.tails);
+        loop {
+            let word = unsafe { *link };
+            let tail = word.map_addr(|addr| addr & !1);
+            if tail.is_null() {
+                return 0;
+            }
+            if tail.addr() == addr {
+                let size = if word.addr() & 1 == 0 {
+                    unsafe { (*tail).size }
+                } else {
+                    mem::size_of::<*mut Tail>()
+                };
+                unsafe { *link = (*tail).next };
+                self.attached_bytes -= u64::try_from(size).unwrap_or(u64::MAX);
+                return size;
+            }
+            link = unsafe { addr_of_mut!((*tail).next) };
+        }
+    }
+}
+
+impl Default for Allocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl super::Allocator for Allocator {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<NonNull<[u8]>> {
+        unsafe { self.try_alloc(layout) }.ok()
+    }
+
+    unsafe fn try_alloc(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.try_alloc_placed(layout, Placement::ANYWHERE)
+            .map(|(alloc, _)| alloc)
+    }
+
+    unsafe fn alloc_zeroed(&mut self, layout: Layout) -> Option<NonNull<[u8]>> {
+        let pristine = self.pristine;
+        let ptr = unsafe { self.alloc(layout) }?;
+        let start = ptr.addr().get();
+        let was_pristine = self
+            .region_slot_of(start)
+            .is_some_and(|slot| start >= pristine[slot]);
+        // only the node of the free region it came from, if that started
+        // right at the allocation, needs zeroing
+        let len = if was_pristine {
+            Ord::min(ptr.len(), mem::size_of::<Node>())
+        } else {
+            ptr.len()
+        };
+        #[cfg(test)]
+        tests::ZEROED_BYTES.with(|zeroed| zeroed.set(zeroed.get() + len));
+        unsafe { ptr.as_mut_ptr().write_bytes(0, len) };
+        Some(ptr)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        unsafe {
+            self.dealloc_returning(ptr, layout);
+        }
+    }
+
+    unsafe fn dealloc_unchecked(&mut self, ptr: *mut u8, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
+        unsafe { self.free_block(ptr, layout) };
+    }
+
+    unsafe fn alloc_near(&mut self, ptr: *mut u8, layout: Layout) -> Option<NonNull<[u8]>> {
+        // staying in the memory region of `ptr` keeps related data together,
+        // so other regions are only tried if it is full
+        let region = self
+            .region_slot_of(ptr.addr())
+            .and_then(|slot| self.regions[slot])
+            .filter(|_| self.region_count > 1);
+        if let Some(region) = region {
+            let placement = Placement {
+                min_addr: region.addr().get(),
+                max_addr: region.addr().get() + region.len(),
+                ..Placement::ANYWHERE
+            };
+            if let Ok((alloc, _)) = self.try_alloc_placed(layout, placement) {
+                return Some(alloc);
+            }
+        }
+        unsafe { self.alloc(layout) }
+    }
+
+    fn usable_size(&self, layout: Layout) -> usize {
+        if layout.size() == 0 {
+            return 0;
+        }
+        Allocator::adjust(layout).map_or(layout.size(), |layout| layout.size())
+    }
+
+    unsafe fn grow_in_place(&mut self, ptr: *mut u8, layout: Layout, new_size: usize) -> bool {
+        // a zero-sized allocation is not in any region, and the guard of an
+        // allocation would have to move
+        layout.size() != 0
+            && self.guard_bytes == 0
+            && unsafe { self.grow_into_next(ptr, layout, new_size) }
+    }
+
+    unsafe fn shrink_in_place(&mut self, ptr: *mut u8, layout: Layout, new_size: usize) -> bool {
+        // a block shrunk to nothing would still be rounded up to a whole
+        // node, and never freed once its allocation is taken to be zero-sized
+        if self.guard_bytes > 0 || new_size == 0 {
+            return false;
+        }
+        let Ok(new_layout) = Layout::from_size_align(new_size, layout.align()) else {
+            return false;
+        };
+        let old_size = unsafe { unwrap_invariant(Allocator::adjust(layout)) }.size();
+        let Some(new_size) = Allocator::adjust(new_layout).map(|layout| layout.size()) else {
+            return false;
+        };
+        let Some(excess_size) = old_size.checked_sub(new_size) else {
+            return false;
+        };
+        if excess_size == 0 {
+            return true;
+        }
+        if excess_size < mem::size_of::<Node>() || self.no_split {
+            return false;
+        }
+        // the tail goes with the rest of the block
+        let excess_size = excess_size + self.detach_tail(ptr.addr() + old_size);
+        unsafe {
+            let excess = ptr.map_addr(|addr| addr + new_size);
+            self.poison_freed(excess, excess_size);
+            self.push_region(unwrap_invariant(NonNull::new(
+                ptr::slice_from_raw_parts_mut(excess, excess_size),
+            )));
+        }
+        true
+    }
+}
+
+struct Nodes<'a> {
+    next: Option<NonNull<Node>>,
+    _alloc: PhantomData<&'a Allocator>,
+}
+
+impl Iterator for Nodes<'_> {
+    type Item = NonNull<Node>;
+
+    fn next(&mut self) -> Option<NonNull<Node>> {
+        let node = self.next?;
+        self.next = unsafe { (*node.as_ptr()).next };
+        Some(node)
+    }
+}
+
+/// How a free region is picked for an allocation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// The first region in the list that fits.
+    #[default]
+    FirstFit,
+    /// The smallest region that fits, which scans the whole list. Ties go to
+    /// the lowest-addressed region, which keeps allocations clustered low and
+    /// leaves the larger stretches of free memory higher up.
+    BestFit,
+    /// The region the last deallocation ended up in, which is likely still
+    /// hot in the cache, if it fits, and otherwise the first region that
+    /// fits. Unlike relying on freed regions being pushed to the front of the
+    /// list, this also holds when coalescing.
+    RecentFirst,
+}
+
+/// Configures an [`Allocator`] option by option.
+///
+/// ```
+/// use allocator::linked_list::{Allocator, Strategy};
+///
+/// let alloc = Allocator::builder()
+///     .coalesce(true)
+///     .poison(0xde)
+///     .strategy(Strategy::BestFit)
+///     .build();
+/// assert!(alloc.is_coalescing());
+/// ```
+pub struct Builder(Allocator);
+
+impl Builder {
+    /// Merge freed memory with adjacent free regions.
+    pub const fn coalesce(mut self, coalesce: bool) -> Self {
+        self.0.coalesce = coalesce;
+        self
+    }
+
+    /// Hand out whole free regions rather than splitting them, see
+    /// [`Allocator::no_split`].
+    pub const fn no_split(mut self, no_split: bool) -> Self {
+        self.0.no_split = no_split;
+        self
+    }
+
+    /// Place allocations at the top of the highest-addressed free region that
+    /// fits, or the smallest one with [`Strategy::BestFit`], and split off
+    /// the rest below them, so that the heap grows down from the top of
+    /// memory. Allocations confined to a range of addresses or within a
+    /// boundary are still placed from the bottom.
+    pub const fn grow_down(mut self, grow_down: bool) -> Self {
+        self.0.grow_down = grow_down;
+        self
+    }
+
+    /// Leave the rest of a free region attached to an allocation, rather
+    /// than splitting it off, if it is smaller than `size` bytes, which
+    /// keeps free regions too small to be of use off the free list. The rest
+    /// is given back when the allocation is freed, and counts towards
+    /// [`attached_bytes`](Allocator::attached_bytes) until then. By default,
+    /// `size` is that of a free list node, and a region that would leave a
+    /// smaller rest is not used for the allocation at all.
+    ///
+    /// Growing an allocation in place likewise has to take up the whole free
+    /// region after it or leave at least `size` bytes of it, or else
+    /// [`realloc`](super::Allocator::realloc) moves the allocation instead,
+    /// trading a copy for less fragmentation.
+    pub const fn min_useful_remainder(mut self, size: usize) -> Self {
+        self.0.min_useful_remainder = size;
+        self
+    }
+
+    /// Pick free regions for allocations with `strategy`.
+    pub const fn strategy(mut self, strategy: Strategy) -> Self {
+        self.0.strategy = strategy;
+        self
+    }
+
+    /// Fill freed memory with `byte`, so that use after free shows up.
+    pub const fn poison(mut self, byte: u8) -> Self {
+        self.0.poison = Some(byte);
+        self
+    }
+
+    /// Fail allocations of more than `size` bytes without looking for a free
+    /// region, so that a single runaway request cannot take the whole heap.
+    pub const fn max_alloc(mut self, size: usize) -> Self {
+        self.0.max_alloc = Some(size);
+        self
+    }
+
+    /// Give up on an allocation after examining `steps` free regions without
+    /// finding a fit, rather than walking the rest of the list, which bounds
+    /// the time an allocation takes. With [`Strategy::BestFit`], the best fit
+    /// among the regions examined is used.
+    pub const fn max_search_steps(mut self, steps: usize) -> Self {
+        self.0.max_search_steps = Some(steps);
+        self
+    }
+
+    /// Fail allocations that would split a free region into more than
+    /// `count` free regions in all, which bounds the length of the free
+    /// list. Freeing can still take it past `count`, and a free region that
+    /// fits an allocation exactly can always be used.
+    pub const fn max_free_blocks(mut self, count: usize) -> Self {
+        self.0.max_free_blocks = Some(count);
+        self
+    }
+
+    /// Follow each allocation with a guard of at least `size` bytes filled
+    /// with a pattern, which [`Allocator::verify`] checks, so that writing
+    /// past the end of an allocation shows up. The guards are neither free
+    /// nor used memory, and allocations with a guard are never resized in
+    /// place.
+    pub const fn guard_bytes(mut self, size: usize) -> Self {
+        self.0.guard_bytes = size;
+        self
+    }
+
+    /// Fail allocations while `count` allocations are outstanding.
+    pub const fn max_allocations(mut self, count: u64) -> Self {
+        self.0.max_allocations = Some(count);
+        self
+    }
+
+    pub const fn build(self) -> Allocator {
+        self.0
+    }
+}
+
+/// The live allocations made with a header holding some `T` in front of
+/// them, such as the tag of [`Allocator::alloc_tagged`] in profiling builds.
+/// They form a doubly linked list through their headers.
+#[cfg(any(feature = "profiling", debug_assertions))]
+struct HeaderList<T> {
+    head: Option<NonNull<Header<T>>>,
+}
+
+#[cfg(any(feature = "profiling", debug_assertions))]
+struct Header<T> {
+    info: T,
+    layout: Layout,
+    prev: Option<NonNull<Header<T>>>,
+    next: Option<NonNull<Header<T>>>,
+}
+
+#[cfg(any(feature = "profiling", debug_assertions))]
+impl<T: Copy> HeaderList<T> {
+    const fn new() -> Self {
+        Self { head: None }
+    }
+
+    /// Returns the layout of an allocation for `layout` with a header in
+    /// front, and the offset of the allocation within it.
+    fn outer(layout: Layout) -> Option<(Layout, usize)> {
+        Layout::new::<Header<T>>().extend(layout).ok()
+    }
+
+    /// Allocates memory for `layout` from `alloc`, with a header holding
+    /// `info` in front of it.
+    ///
+    /// This function is unsafe for the same reasons as
+    /// [`alloc`](super::Allocator::alloc).
+    unsafe fn alloc(
+        &mut self,
+        alloc: &mut Allocator,
+        layout: Layout,
+        info: T,
+    ) -> Option<NonNull<[u8]>> {
+        let (outer, offset) = Self::outer(layout)?;
+        let block = unsafe { super::Allocator::alloc(alloc, outer) }?;
+        let header = block.as_mut_ptr().cast::<Header<T>>();
+        unsafe {
+            header.write(Header {
+                info,
+                layout,
+                prev: None,
+                next: self.head,
+            });
+            if let Some(next) = self.head {
+                (*next.as_ptr()).prev = NonNull::new(header);
+            }
+        }
+        self.head = NonNull::new(header);
+        NonNull::new(ptr::slice_from_raw_parts_mut(
+            block.as_mut_ptr().map_addr(|addr| addr + offset),
+            layout.size(),
+        ))
+    }
+
+    /// Frees an allocation made by [`HeaderList::alloc`] to `alloc`.
+    ///
+    /// This function is unsafe because the caller must guarantee that `ptr` is
+    /// a live allocation in this list made with `layout`.
+    unsafe fn dealloc(&mut self, alloc: &mut Allocator, ptr: *mut u8, layout: Layout) {
+        let (outer, offset) = unsafe { unwrap_invariant(Self::outer(layout)) };
+        let header = ptr.map_addr(|addr| addr - offset).cast::<Header<T>>();
+        unsafe {
+            let Header { prev, next, .. } = header.read();
+            match prev {
+                Some(prev) => (*prev.as_ptr()).next = next,
+                None => self.head = next,
+            }
+            if let Some(next) = next {
+                (*next.as_ptr()).prev = prev;
+            }
+            super::Allocator::dealloc(alloc, header.cast(), outer);
+        }
+    }
+
+    /// Resizes an allocation made by [`HeaderList::alloc`] to `new_size`
+    /// bytes, keeping its header info. If it cannot be resized in place, it
+    /// is moved to a new allocation at the front of the list.
+    ///
+    /// This function is unsafe for the same reasons as [`HeaderList::dealloc`].
+    #[cfg(feature = "profiling")]
+    unsafe fn realloc(
+        &mut self,
+        alloc: &mut Allocator,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> Option<NonNull<[u8]>> {
+        let new_layout = Layout::from_size_align(new_size, layout.align()).ok()?;
+        let (outer, offset) = unsafe { unwrap_invariant(Self::outer(layout)) };
+        let (new_outer, _) = Self::outer(new_layout)?;
+        let header = ptr.map_addr(|addr| addr - offset).cast::<Header<T>>();
+        let in_place = unsafe {
+            if new_outer.size() <= outer.size() {
+                super::Allocator::shrink_in_place(alloc, header.cast(), outer, new_outer.size())
+            } else {
+                super::Allocator::grow_in_place(alloc, header.cast(), outer, new_outer.size())
+            }
+        };
+        if in_place {
+            unsafe { (*header).layout = new_layout };
+            return NonNull::new(ptr::slice_from_raw_parts_mut(ptr, new_size));
+        }
+        let info = unsafe { (*header).info };
+        let new = unsafe { self.alloc(alloc, new_layout, info) }?;
+        unsafe {
+            ptr::copy_nonoverlapping(ptr, new.as_mut_ptr(), Ord::min(layout.size(), new_size));
+            self.dealloc(alloc, ptr, layout);
+        }
+        Some(new)
+    }
+
+    /// Returns the address, size and header info of each allocation, most
+    /// recent first.
+    fn iter(&self) -> impl Iterator<Item = (usize, usize, T)> + '_ {
+        let mut next = self.head;
+        core::iter::from_fn(move || {
+            let header = next?;
+            let Header {
+                info,
+                layout,
+                next: after,
+                ..
+            } = unsafe { header.as_ptr().read() };
+            next = after;
+            let (_, offset) = unsafe { unwrap_invariant(Self::outer(layout)) };
+            Some((header.addr().get() + offset, layout.size(), info))
+        })
+    }
+}
+
+/// Ways in which [`Allocator::verify`] can find the free list to be corrupt.
+/// Nodes are identified by their address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The list loops back on itself.
+    Cycle,
+    /// A node is not aligned for a node.
+    Misaligned(usize),
+    /// A node's size is too small to hold the node itself.
+    TooSmall(usize),
+    /// A node's region runs past the end of the address space.
+    SizeOverflow(usize),
+    /// The regions of two nodes overlap.
+    Overlap(usize, usize),
+    /// The guard after an allocation, identified by where it starts, was
+    /// written to, or its link to the next guard is broken.
+    GuardOverwritten(usize),
+}
+
+/// A handle to a memory region added to an [`Allocator`], for
+/// [`Allocator::forget_region`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegionId {
+    // the slot of the region table the region is in
+    slot: usize,
+    // how often the slot had been emptied when the region was added
+    generation: u32,
+}
+
+/// Reasons a memory region cannot be added to the free list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegionError {
+    /// The region is shorter than a free list node, which includes empty
+    /// regions.
+    TooSmall,
+    /// The region does not start at a suitably aligned address for a free
+    /// list node.
+    Misaligned,
+    /// The region runs past the end of the address space.
+    Overflow,
+    /// The allocator already has [`MAX_REGIONS`] regions.
+    TooManyRegions,
+}
+
+/// Reasons [`Allocator::alloc_or_grow`] can fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GrowError {
+    /// The allocation failed, either for a reason more memory would not fix
+    /// or after `grow` had no region to give or its region was added.
+    Alloc(AllocError),
+    /// The region from `grow` could not be added to the allocator, so it is
+    /// handed back to the caller, still unused.
+    Rejected(NonNull<[u8]>, RegionError),
+}
+
+/// Where in the free regions an allocation may be placed.
+#[derive(Clone, Copy)]
+struct Placement {
+    /// The allocation must start at or above this address.
+    min_addr: usize,
+    /// The allocation must end at or below this address.
+    max_addr: usize,
+    /// The allocation must not cross a multiple of this power of two.
+    boundary: Option<usize>,
+}
+
+impl Placement {
+    const ANYWHERE: Placement = Placement {
+        min_addr: 0,
+        max_addr: usize::MAX,
+        boundary: None,
+    };
+}
+
+/// The two adjacent allocations [`Allocator::split_free_block`] carves out of
+/// a free region, in address order.
+pub type SplitBlock = (NonNull<[u8]>, NonNull<[u8]>);
+
+/// How much of a memory region is free and used, see
+/// [`Allocator::region_stats`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegionStats {
+    pub free_bytes: usize,
+    pub used_bytes: usize,
+    /// The number of free regions the free memory is split into.
+    pub free_blocks: usize,
+}
+
+/// What a pass of [`Allocator::merge_all`] or
+/// [`Allocator::defragment_incremental`] did, for deciding whether another is
+/// worth it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DefragStats {
+    /// The number of free regions merged into the one in front of them.
+    pub merges: usize,
+    pub before_largest: usize,
+    pub after_largest: usize,
+    pub before_blocks: usize,
+    pub after_blocks: usize,
+    /// Whether there is more work left for a later call.
+    pub more: bool,
+}
+
+/// A memory region added to an allocator and how much of it is free, see
+/// [`Allocator::regions`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegionInfo {
+    pub id: RegionId,
+    pub start: usize,
+    pub size: usize,
+    pub free_bytes: usize,
+    /// The number of free regions the free memory is split into.
+    pub free_blocks: usize,
+}
+
+/// Whether memory in an allocator is free or allocated, see
+/// [`Allocator::status_of`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockStatus {
+    Free,
+    Allocated,
+}
+
+/// The byte the guards after allocations are filled with.
+const GUARD_BYTE: u8 = 0xfd;
+
+// the end of the guard after an allocation, linking it to the guard after the
+// previous allocation
+struct Guard {
+    next: Option<NonNull<Guard>>,
+}
+
+// the record in the bytes left attached to the end of an allocation, because
+// they were too few to split off, so that freeing the allocation with the
+// layout it was made with gives them back
+struct Tail {
+    // the next tail, with the low bit set if that one is a single word and so
+    // has no room for its size
+    next: *mut Tail,
+    size: usize,
+}
+
+// node: Node is the header of a memory region of size node.size >=
+// size_of::<Node>() bytes, except for the dummy node at the start of
+// Allocator
+struct Node {
+    size: usize,
+    next: Option<NonNull<Node>>,
+}
+
+impl Node {
+    fn end(this: *mut Node) -> *mut u8 {
+        this.cast::<u8>()
+            .map_addr(|addr| addr + unsafe { (*this).size })
+    }
+
+    /// Like [`Node::end`], but `None` if the end is past the address space,
+    /// which only a corrupted size can cause.
+    fn checked_end(this: *mut Node) -> Option<*mut u8> {
+        let end = this.addr().checked_add(unsafe { (*this).size })?;
+        Some(this.cast::<u8>().with_addr(end))
+    }
+
+    /// Returns the allocation that would be carved out of the free region
+    /// `this` for `layout`, if it fits. Without a `min_remainder`, or if
+    /// fewer bytes than that would be left over, the allocation takes up the
+    /// rest of the region. Any gap left in front of the
+    /// allocation by its alignment can hold a node. With a `boundary`, the
+    /// first `layout.size()` bytes of the allocation do not cross a multiple
+    /// of it.
+    fn alloc_from_region(
+        this: *mut Self,
+        layout: Layout,
+        min_remainder: Option<usize>,
+        boundary: Option<usize>,
+    ) -> Option<NonNull<[u8]>> {
+        let mut alloc_start = this.cast::<u8>().try_align_up(layout.align())?;
+        let lead_size = alloc_start.addr() - this.addr();
+        if 0 < lead_size && lead_size < mem::size_of::<Node>() {
+            // leave room for the gap in front of the allocation to hold a node
+            alloc_start = alloc_start
+                .with_addr(this.addr().checked_add(mem::size_of::<Node>())?)
+                .try_align_up(layout.align())?;
+        }
+        if let Some(boundary) = boundary {
+            if layout.size() > boundary {
+                return None;
+            }
+            let offset = alloc_start.addr() & (boundary - 1);
+            if offset + layout.size() > boundary {
+                // move up to the start of the next window, or the one after
+                // that if the gap in front could not hold a node
+                let mut window = alloc_start.addr().checked_add(boundary - offset)?;
+                if window - this.addr() < mem::size_of::<Node>() {
+                    window = window.checked_add(boundary)?;
+                }
+                // the aligned start is still a multiple of the boundary
+                alloc_start = alloc_start.with_addr(window).try_align_up(layout.align())?;
+            }
+        }
+        let alloc_end = alloc_start.with_addr(alloc_start.addr().checked_add(layout.size())?);
+        let region_end = Node::checked_end(this)?;
+
+        if alloc_end > region_end {
+            return None;
+        }
+
+        let excess_size = region_end.addr() - alloc_end.addr();
+        if let Some(min_remainder) = min_remainder {
+            if excess_size == 0 || excess_size >= Ord::max(min_remainder, mem::size_of::<Node>()) {
+                return NonNull::new(ptr::slice_from_raw_parts_mut(alloc_start, layout.size()));
+            }
+            if min_remainder <= mem::size_of::<Node>() {
+                // a sliver that can neither be split off nor left attached
+                return None;
+            }
+        }
+        NonNull::new(ptr::slice_from_raw_parts_mut(
+            alloc_start,
+            region_end.addr() - alloc_start.addr(),
+        ))
+    }
+
+    /// Like [`alloc_from_region`](Node::alloc_from_region), but carves the
+    /// allocation out of the top of the free region, leaving the rest below
+    /// it. Any gap left after the allocation by its alignment can hold a
+    /// node. If the rest is too small to split off, the allocation takes it
+    /// up, as long as the region is aligned enough to start it.
+    fn alloc_from_top(
+        this: *mut Self,
+        layout: Layout,
+        min_remainder: Option<usize>,
+    ) -> Option<NonNull<[u8]>> {
+        let region_end = Node::checked_end(this)?.addr();
+        let start_below = |end: usize| {
+            let start = end.checked_sub(layout.size())? & !(layout.align() - 1);
+            (start >= this.addr()).then_some(start)
+        };
+        let mut start = start_below(region_end)?;
+        let tail_size = region_end - (start + layout.size());
+        if 0 < tail_size && tail_size < mem::size_of::<Node>() {
+            // leave room for the gap after the allocation to hold a node
+            start = start_below(region_end.checked_sub(mem::size_of::<Node>())?)?;
+        }
+        let lead_size = start - this.addr();
+        if let Some(min_remainder) = min_remainder {
+            if lead_size == 0 || lead_size >= Ord::max(min_remainder, mem::size_of::<Node>()) {
+                return NonNull::new(ptr::slice_from_raw_parts_mut(
+                    this.cast::<u8>().with_addr(start),
+                    layout.size(),
+                ));
+            }
+            if min_remainder <= mem::size_of::<Node>() {
+                // a sliver that can neither be split off nor left attached
+                return None;
+            }
+        }
+        if !this.is_aligned_to(layout.align()) {
+            return None;
+        }
+        NonNull::new(ptr::slice_from_raw_parts_mut(
+            this.cast::<u8>(),
+            start + layout.size() - this.addr(),
+        ))
+    }
+}
+
+/// Pokes at the free list behind the allocator's back, to check that the
+/// corruption gets caught.
+#[cfg(test)]
+pub(crate) mod corrupt {
+    use core::ptr::NonNull;
+
+    use super::{Allocator, Node};
+
+    fn nth(alloc: &Allocator, index: usize) -> NonNull<Node> {
+        alloc.nodes().nth(index).expect("no such node")
+    }
+
+    /// Overwrites the size of the `index`th node in list order.
+    pub fn set_size(alloc: &mut Allocator, index: usize, size: usize) {
+        unsafe { (*nth(alloc, index).as_ptr()).size = size };
+    }
+
+    /// Points the `from`th node in list order at the `to`th one.
+    pub fn link(alloc: &mut Allocator, from: usize, to: usize) {
+        let to = nth(alloc, to);
+        unsafe { (*nth(alloc, from).as_ptr()).next = Some(to) };
+    }
+
+    /// Pushes a node for `size` bytes at `ptr`, which need not be aligned,
+    /// to the front of the list.
+    ///
+    /// This function is unsafe because the caller must guarantee that `ptr` is
+    /// valid for writes of a node.
+    pub unsafe fn push_node(alloc: &mut Allocator, ptr: *mut u8, size: usize) {
+        let node = Node {
+            size,
+            next: alloc.head.next,
+        };
+        unsafe { ptr.cast::<Node>().write_unaligned(node) };
+        alloc.head.next = NonNull::new(ptr.cast());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        alloc::Layout,
+        cell::{Cell, SyncUnsafeCell},
+        mem,
+        ptr::{self, addr_of_mut, slice_from_raw_parts_mut, NonNull},
+    };
+
+    use static_assertions::const_assert_eq;
+    use std::{format, panic, string::String, vec::Vec};
+
+    use super::{
+        corrupt, Allocator, BlockStatus, DefragStats, GrowError, Node, RegionError, RegionId,
+        RegionInfo, RegionStats, Strategy, VerifyError, MAX_REGIONS,
+    };
+    use crate::{
+        test_support::{check_non_overlapping, check_within},
+        AllocError, Allocator as _, PAGE_SIZE,
+    };
+
+    #[repr(align(8))]
+    struct MemPool<const N: usize>([u8; N]);
+    const_assert_eq!(mem::align_of::<MemPool<1>>(), mem::align_of::<Node>());
+
+    std::thread_local! {
+        /// How many bytes this thread's `alloc_zeroed` calls have zeroed.
+        pub(super) static ZEROED_BYTES: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[test]
+    fn test() {
+        const HEAP_SIZE: usize = 1 << 12;
+        static HEAP1: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        static HEAP2: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let mut alloc = Allocator::new();
+        unsafe {
+            alloc.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP1.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+            alloc.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP2.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+        }
+        let l1 = Layout::new::<[u8; HEAP_SIZE]>();
+        let l2 = Layout::new::<u64>();
+        let l3 = Layout::new::<u64>();
+        unsafe {
+            let p1 = alloc.alloc(l1).unwrap();
+            let p2 = alloc.alloc(l2).unwrap();
+            let p3 = alloc.alloc(l3).unwrap();
+            alloc.dealloc(p1.as_mut_ptr(), l1);
+            alloc.dealloc(p3.as_mut_ptr(), l2);
+            alloc.dealloc(p2.as_mut_ptr(), l2);
+        }
+    }
+
+    fn free_list(alloc: &Allocator) -> Vec<(usize, usize)> {
+        alloc
+            .nodes()
+            .map(|node| (node.addr().get(), unsafe { (*node.as_ptr()).size }))
+            .collect()
+    }
+
+    #[test]
+    fn dealloc_unchecked() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let mut alloc = Allocator::new();
+        unsafe {
+            alloc.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+        }
+        let l = Layout::new::<[u64; 3]>();
+        unsafe {
+            let p = alloc.alloc(l).unwrap();
+            alloc.dealloc(p.as_mut_ptr(), l);
+            let checked = free_list(&alloc);
+            let p = alloc.alloc(l).unwrap();
+            alloc.dealloc_unchecked(p.as_mut_ptr(), l);
+            assert_eq!(free_list(&alloc), checked);
+        }
+    }
+
+    #[test]
+    fn dealloc_unchecked_coalescing() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::coalescing();
+        let l = Layout::new::<[u64; 4]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let p = alloc.alloc(l).unwrap();
+            let q = alloc.alloc(l).unwrap();
+            alloc.dealloc_unchecked(p.as_mut_ptr(), l);
+            alloc.dealloc_unchecked(q.as_mut_ptr(), l);
+        }
+        // only the checks are skipped, not the merging
+        assert_eq!(free_list(&alloc), [(heap.addr(), HEAP_SIZE)]);
+        assert_eq!(alloc.allocation_count(), 0);
+    }
+
+    #[test]
+    fn small_regions() {
+        static HEAP: SyncUnsafeCell<MemPool<{ mem::size_of::<Node>() }>> =
+            SyncUnsafeCell::new(MemPool([0; mem::size_of::<Node>()]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        unsafe {
+            assert_eq!(
+                alloc.try_add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, 0)).unwrap()),
+                Err(RegionError::TooSmall)
+            );
+            assert_eq!(
+                alloc.try_add_free_region(
+                    NonNull::new(slice_from_raw_parts_mut(heap, mem::size_of::<Node>() - 1))
+                        .unwrap()
+                ),
+                Err(RegionError::TooSmall)
+            );
+            assert!(free_list(&alloc).is_empty());
+
+            alloc
+                .try_add_free_region(
+                    NonNull::new(slice_from_raw_parts_mut(heap, mem::size_of::<Node>())).unwrap(),
+                )
+                .unwrap();
+            let l = Layout::new::<u8>();
+            let p = alloc.alloc(l).unwrap();
+            assert_eq!(p.as_mut_ptr(), heap);
+            assert!(alloc.alloc(l).is_none());
+            alloc.dealloc(p.as_mut_ptr(), l);
+            assert_eq!(free_list(&alloc), [(heap.addr(), mem::size_of::<Node>())]);
+        }
+    }
+
+    #[test]
+    fn overflowing_region() {
+        let mut alloc = Allocator::new();
+        let start = ptr::null_mut::<u8>().with_addr(usize::MAX & !(mem::align_of::<Node>() - 1));
+        let region = NonNull::new(slice_from_raw_parts_mut(start, 64)).unwrap();
+        assert_eq!(
+            unsafe { alloc.try_add_free_region(region) },
+            Err(RegionError::Overflow)
+        );
+        assert!(free_list(&alloc).is_empty());
+    }
+
+    #[test]
+    fn first_free_above() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        // free blocks at offsets 0x80, 0x00 and 0x40, added out of order
+        for offset in [0x80, 0x00, 0x40] {
+            unsafe {
+                alloc.add_free_region(
+                    NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(offset), 0x20))
+                        .unwrap(),
+                );
+            }
+        }
+        let base = heap.addr();
+        assert_eq!(alloc.first_free_above(0), Some((base, 0x20)));
+        assert_eq!(alloc.first_free_above(base), Some((base, 0x20)));
+        assert_eq!(alloc.first_free_above(base + 1), Some((base + 0x40, 0x20)));
+        assert_eq!(
+            alloc.first_free_above(base + 0x41),
+            Some((base + 0x80, 0x20))
+        );
+        assert_eq!(alloc.first_free_above(base + 0x81), None);
+    }
+
+    #[test]
+    fn dealloc_returning() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::coalescing();
+        let l = Layout::new::<[u64; 4]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let a = alloc.alloc(l).unwrap().as_mut_ptr();
+            let b = alloc.alloc(l).unwrap().as_mut_ptr();
+            let c = alloc.alloc(l).unwrap().as_mut_ptr();
+            assert_eq!(alloc.dealloc_returning(b, l), (b.addr(), 32));
+            assert_eq!(alloc.dealloc_returning(a, l), (a.addr(), 64));
+            assert_eq!(alloc.dealloc_returning(c, l), (a.addr(), HEAP_SIZE));
+        }
+        assert_eq!(free_list(&alloc), [(heap.addr(), HEAP_SIZE)]);
+    }
+
+    #[test]
+    fn split_provenance() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP1: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        static HEAP2: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let regions = [HEAP1.get(), HEAP2.get()].map(|heap| {
+            NonNull::new(slice_from_raw_parts_mut(
+                unsafe { addr_of_mut!((*heap).0) }.cast::<u8>(),
+                HEAP_SIZE,
+            ))
+            .unwrap()
+        });
+        let mut alloc = Allocator::new();
+        for region in regions {
+            unsafe { alloc.add_free_region(region) };
+        }
+        // the first allocation splits the region added last, and the ones
+        // after it are carved out of the excess
+        let l = Layout::new::<[u64; 4]>();
+        let allocs = [(); 4].map(|_| (unsafe { alloc.alloc(l) }.unwrap(), l));
+        for (p, l) in allocs {
+            // writing through the pointer is only allowed (under Miri) if it
+            // kept the provenance of the region it came from
+            unsafe { p.as_mut_ptr().write_bytes(0xAA, l.size()) };
+        }
+        assert_eq!(check_non_overlapping(&allocs), Ok(()));
+        assert_eq!(check_within(&allocs, &regions[1..]), Ok(()));
+    }
+
+    #[test]
+    fn defragment_incremental() {
+        const HEAP_SIZE: usize = 1 << 9;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 4]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let blocks = [(); HEAP_SIZE / 32].map(|_| alloc.alloc(l).unwrap());
+            for i in [5, 12, 0, 9, 3, 15, 7, 1, 10, 14, 2, 8, 13, 4, 11, 6] {
+                alloc.dealloc(blocks[i].as_mut_ptr(), l);
+            }
+        }
+        assert_eq!(free_list(&alloc).len(), 16);
+
+        let mut calls = 0;
+        while alloc.defragment_incremental(4).more {
+            calls += 1;
+            assert!(free_list(&alloc).len() >= 16 - 4 * calls);
+        }
+        assert!(calls > 1);
+        assert_eq!(free_list(&alloc), [(heap.addr(), HEAP_SIZE)]);
+    }
+
+    #[test]
+    fn no_split() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let l = Layout::new::<[u64; 3]>();
+        for mut alloc in [Allocator::new(), Allocator::no_split()] {
+            // a pool of 32 byte blocks
+            for offset in (0..HEAP_SIZE).step_by(32) {
+                unsafe {
+                    alloc.add_free_region(
+                        NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(offset), 32))
+                            .unwrap(),
+                    );
+                }
+            }
+            let Some(first) = (unsafe { alloc.alloc(l) }) else {
+                // splitting would leave a sliver too small for a node
+                assert!(!alloc.no_split);
+                continue;
+            };
+            assert_eq!(first.len(), l.size());
+            assert_eq!(alloc.attached_bytes(), 8);
+            // freeing with the requested layout gives back the whole block
+            for _ in 0..8 {
+                unsafe {
+                    alloc.dealloc(first.as_mut_ptr(), l);
+                    assert_eq!(free_list(&alloc).len(), 4);
+                    assert_eq!(alloc.alloc(l), Some(first));
+                }
+            }
+            assert_eq!(free_list(&alloc).len(), 3);
+            assert!(free_list(&alloc).iter().all(|&(_, size)| size == 32));
+        }
+    }
+
+    #[test]
+    fn dump() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 4]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let p = alloc.alloc(l).unwrap();
+            alloc.alloc(l).unwrap();
+            alloc.dealloc(p.as_mut_ptr(), l);
+        }
+        let mut out = String::new();
+        alloc.dump(&mut out).unwrap();
+        let expected = format!(
+            "{:#x} 32\n{:#x} 64\n2 free regions, 96 bytes free, largest 64 bytes\n",
+            heap.addr(),
+            heap.addr() + 64
+        );
+        assert_eq!(out, expected);
+    }
+
+    // runs with and without the unsafe_no_panic feature, which must not change
+    // the behaviour for valid inputs
+    #[test]
+    fn unchecked_invariants() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::coalescing();
+        let (l1, l2) = (Layout::new::<[u64; 4]>(), Layout::new::<[u64; 8]>());
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let p1 = alloc.alloc(l1).unwrap();
+            let p2 = alloc.alloc(l2).unwrap();
+            let p3 = alloc.alloc(l1).unwrap();
+            assert_eq!(
+                [p1, p2, p3].map(|p| p.as_mut_ptr().addr() - heap.addr()),
+                [0, 32, 96]
+            );
+            alloc.dealloc(p2.as_mut_ptr(), l2);
+            assert!(alloc.grow_in_place(p1.as_mut_ptr(), l1, 64));
+            assert_eq!(
+                free_list(&alloc),
+                [(heap.addr() + 64, 32), (heap.addr() + 128, 128)]
+            );
+            alloc.dealloc(p3.as_mut_ptr(), l1);
+            alloc.dealloc(p1.as_mut_ptr(), Layout::new::<[u64; 8]>());
+        }
+        assert_eq!(free_list(&alloc), [(heap.addr(), HEAP_SIZE)]);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "misaligned free list node")]
+    fn misaligned_node() {
+        const HEAP_SIZE: usize = 1 << 6;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 2]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            alloc.alloc(l).unwrap();
+            // dealloc_unchecked skips the alignment check in add_free_region
+            alloc.dealloc_unchecked(heap.wrapping_add(4), l);
+        }
+    }
+
+    #[test]
+    fn absorb_following() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 4]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, 32)).unwrap());
+            let p = alloc.alloc(l).unwrap().as_mut_ptr();
+
+            let gap = NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(40), 64)).unwrap();
+            assert_eq!(alloc.absorb_following(p, l, gap), None);
+            assert!(free_list(&alloc).is_empty());
+
+            let extra = NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(32), 64)).unwrap();
+            let grown = alloc.absorb_following(p, l, extra).unwrap();
+            assert_eq!(grown.as_mut_ptr(), p);
+            assert_eq!(grown.len(), 96);
+            assert!(free_list(&alloc).is_empty());
+            // the region grew instead of another one being added
+            assert_eq!(alloc.region_count(), 1);
+            assert_eq!(alloc.heap_end(), Some(heap.addr() + 96));
+
+            // and it all comes back as one block
+            let grown_layout = Layout::from_size_align(96, 8).unwrap();
+            alloc.dealloc(p, grown_layout);
+            assert_eq!(free_list(&alloc), [(heap.addr(), 96)]);
+
+            // an allocation that does not end its region cannot take more
+            let q = alloc.alloc(l).unwrap().as_mut_ptr();
+            let extra = NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(96), 32)).unwrap();
+            assert_eq!(alloc.absorb_following(q, l, extra), None);
+            assert_eq!(alloc.heap_end(), Some(heap.addr() + 96));
+        }
+    }
+
+    #[test]
+    fn builder() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::builder()
+            .coalesce(true)
+            .no_split(false)
+            .poison(0xde)
+            .strategy(Strategy::BestFit)
+            .build();
+        assert!(alloc.is_coalescing());
+        assert!(!alloc.is_no_split());
+        assert_eq!(alloc.poison(), Some(0xde));
+        assert_eq!(alloc.strategy(), Strategy::BestFit);
+
+        let l = Layout::new::<[u8; 32]>();
+        unsafe {
+            // the larger region ends up at the front of the list
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, 32)).unwrap());
+            alloc.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(64), 64)).unwrap(),
+            );
+            let p = alloc.alloc(l).unwrap();
+            assert_eq!(p.as_mut_ptr(), heap);
+            p.as_mut_ptr().write_bytes(0, 32);
+            alloc.dealloc(p.as_mut_ptr(), l);
+            let freed = &*slice_from_raw_parts_mut(heap, 32);
+            assert!(freed[mem::size_of::<Node>()..].iter().all(|&b| b == 0xde));
+        }
+    }
+
+    #[test]
+    fn corruption() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        fn at(offset: usize) -> *mut u8 {
+            unsafe { addr_of_mut!((*HEAP.get()).0) }
+                .cast::<u8>()
+                .wrapping_add(offset)
+        }
+        // free regions at 0..64, 64..128 and 128..192, in reverse list order
+        let fresh = || {
+            let mut alloc = Allocator::new();
+            for offset in [0, 64, 128] {
+                unsafe {
+                    alloc.add_free_region(
+                        NonNull::new(slice_from_raw_parts_mut(at(offset), 64)).unwrap(),
+                    );
+                }
+            }
+            assert_eq!(alloc.verify(), Ok(()));
+            alloc
+        };
+
+        type Corruption = fn(&mut Allocator);
+        let cases: [(Corruption, VerifyError); 7] = [
+            (
+                |alloc| unsafe { corrupt::push_node(alloc, at(32), 32) },
+                VerifyError::Overlap(at(32).addr(), at(0).addr()),
+            ),
+            (
+                |alloc| corrupt::set_size(alloc, 1, 96),
+                VerifyError::Overlap(at(128).addr(), at(64).addr()),
+            ),
+            (|alloc| corrupt::link(alloc, 2, 0), VerifyError::Cycle),
+            (|alloc| corrupt::link(alloc, 1, 1), VerifyError::Cycle),
+            (
+                |alloc| corrupt::set_size(alloc, 2, usize::MAX),
+                VerifyError::SizeOverflow(at(0).addr()),
+            ),
+            (
+                |alloc| unsafe { corrupt::push_node(alloc, at(196), 32) },
+                VerifyError::Misaligned(at(196).addr()),
+            ),
+            (
+                |alloc| corrupt::set_size(alloc, 0, 8),
+                VerifyError::TooSmall(at(128).addr()),
+            ),
+        ];
+        for (corrupt, expected) in cases {
+            let mut alloc = fresh();
+            corrupt(&mut alloc);
+            assert_eq!(alloc.verify(), Err(expected));
+        }
+    }
+
+    #[cfg(feature = "profiling")]
+    #[test]
+    fn alloc_tagged() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let (l1, l2) = (Layout::new::<u64>(), Layout::new::<[u64; 2]>());
+        let (p1, p2, p3) = unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            (
+                alloc.alloc_tagged(l1, 7).unwrap(),
+                alloc.alloc_tagged(l2, 42).unwrap(),
+                alloc.alloc_tagged(l1, 7).unwrap(),
+            )
+        };
+        assert!(check_non_overlapping(&[(p1, l1), (p2, l2), (p3, l1)]).is_ok());
+        unsafe { alloc.dealloc_tagged(p1.as_mut_ptr(), l1) };
+
+        let mut out = String::new();
+        alloc.dump(&mut out).unwrap();
+        let tagged: Vec<_> = out
+            .lines()
+            .filter(|line| line.starts_with("tagged"))
+            .collect();
+        assert_eq!(
+            tagged,
+            [
+                format!("tagged {:#x} 8 7", p3.as_mut_ptr().addr()),
+                format!("tagged {:#x} 16 42", p2.as_mut_ptr().addr()),
+            ]
+        );
+
+        unsafe {
+            alloc.dealloc_tagged(p3.as_mut_ptr(), l1);
+            alloc.dealloc_tagged(p2.as_mut_ptr(), l2);
+        }
+        out.clear();
+        alloc.dump(&mut out).unwrap();
+        assert!(!out.contains("tagged"));
+    }
+
+    #[test]
+    fn realloc() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let (l32, l64) = (Layout::new::<[u64; 4]>(), Layout::new::<[u64; 8]>());
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let p = alloc.alloc(l64).unwrap().as_mut_ptr();
+            let fence = alloc.alloc(l64).unwrap().as_mut_ptr();
+
+            // shrinking frees the tail in place
+            let shrunk = alloc.realloc(p, l64, 32).unwrap();
+            assert_eq!(shrunk.as_mut_ptr(), p);
+            assert_eq!(shrunk.len(), 32);
+            assert!(free_list(&alloc).contains(&(p.addr() + 32, 32)));
+
+            // growing takes the free tail back in place
+            let grown = alloc.realloc(p, l32, 64).unwrap();
+            assert_eq!(grown.as_mut_ptr(), p);
+            assert!(!free_list(&alloc).contains(&(p.addr() + 32, 32)));
+
+            // the allocation after it is in the way, so it has to move
+            p.write_bytes(0xab, 64);
+            let moved = alloc.realloc(p, l64, 96).unwrap();
+            assert_ne!(moved.as_mut_ptr(), p);
+            assert!(moved.as_ref()[..64].iter().all(|&b| b == 0xab));
+            assert!(free_list(&alloc).contains(&(p.addr(), 64)));
+            assert_eq!(fence, p.wrapping_add(64));
+        }
+    }
+
+    #[test]
+    fn realloc_to_zero() {
+        let mut heap = [0u64; 8];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<u64>();
+        unsafe {
+            alloc.add_free_region(NonNull::from(heap));
+            let p = alloc.alloc(l).unwrap().as_mut_ptr();
+            assert!(!alloc.shrink_in_place(p, l, 0));
+            let z = alloc.realloc(p, l, 0).unwrap();
+            assert_eq!(z.len(), 0);
+            assert_eq!(alloc.allocation_count(), 0);
+            assert_eq!(alloc.used_bytes(), 0);
+            alloc.dealloc(z.as_mut_ptr(), Layout::from_size_align(0, 8).unwrap());
+        }
+        assert_eq!(alloc.used_bytes(), 0);
+    }
+
+    #[test]
+    fn alloc_contiguous_pages() {
+        #[repr(align(4096))]
+        struct Pages<const N: usize>([u8; N]);
+        const HEAP_SIZE: usize = 5 * PAGE_SIZE;
+        static HEAP: SyncUnsafeCell<Pages<HEAP_SIZE>> = SyncUnsafeCell::new(Pages([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        unsafe {
+            alloc.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    heap.wrapping_add(64),
+                    HEAP_SIZE - 64,
+                ))
+                .unwrap(),
+            );
+            let run = alloc.alloc_contiguous_pages(3).unwrap();
+            assert_eq!(run.as_mut_ptr(), heap.wrapping_add(PAGE_SIZE));
+            assert_eq!(run.len(), 3 * PAGE_SIZE);
+            // the leading and trailing remainders are both free again
+            let mut free = free_list(&alloc);
+            free.sort();
+            assert_eq!(
+                free,
+                [
+                    (heap.addr() + 64, PAGE_SIZE - 64),
+                    (heap.addr() + 4 * PAGE_SIZE, PAGE_SIZE)
+                ]
+            );
+            assert!(alloc.alloc_contiguous_pages(2).is_none());
+            assert!(alloc.alloc_contiguous_pages(0).is_none());
+        }
+    }
+
+    #[test]
+    fn max_alloc() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::builder().max_alloc(64).build();
+        assert_eq!(alloc.max_alloc(), Some(64));
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            assert_eq!(
+                alloc.try_alloc(Layout::new::<[u8; 65]>()),
+                Err(AllocError::ExceedsLimit)
+            );
+            assert!(alloc.alloc(Layout::new::<[u8; 65]>()).is_none());
+            assert!(alloc.alloc(Layout::new::<[u8; 64]>()).is_some());
+        }
+    }
+
+    #[test]
+    fn max_alloc_grow() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::builder().max_alloc(64).build();
+        let l = Layout::new::<[u8; 32]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let p = alloc.alloc(l).unwrap();
+            // the free memory right after it would fit, but not the limit
+            assert!(!alloc.grow_in_place(p.as_mut_ptr(), l, 65));
+            assert!(alloc.grow_in_place(p.as_mut_ptr(), l, 64));
+        }
+    }
+
+    #[test]
+    fn region_stats() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP1: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        static HEAP2: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap1 = unsafe { addr_of_mut!((*HEAP1.get()).0) }.cast::<u8>();
+        let heap2 = unsafe { addr_of_mut!((*HEAP2.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 4]>();
+        let [id1, id2] = unsafe {
+            let ids = [heap1, heap2].map(|heap| {
+                alloc.add_free_region(
+                    NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap(),
+                )
+            });
+            // the second region is at the front of the list, so it fills first
+            let allocs: Vec<_> = (0..6).map(|_| alloc.alloc(l).unwrap()).collect();
+            alloc.dealloc(allocs[1].as_mut_ptr(), l);
+            ids
+        };
+        assert_eq!(alloc.region_count(), 2);
+        assert_eq!(
+            alloc.region_stats(id1),
+            Some(RegionStats {
+                free_bytes: HEAP_SIZE,
+                used_bytes: 0,
+                free_blocks: 1,
+            })
+        );
+        assert_eq!(
+            alloc.region_stats(id2),
+            Some(RegionStats {
+                free_bytes: HEAP_SIZE - 5 * 32,
+                used_bytes: 5 * 32,
+                free_blocks: 2,
+            })
+        );
+        let region1 = unsafe { alloc.forget_region(id1) }.unwrap();
+        assert_eq!(alloc.region_stats(id1), None);
+        // a region added later in the same slot has another id
+        let id3 = unsafe { alloc.add_free_region(region1) };
+        assert_ne!(id3, id1);
+        assert_eq!(alloc.region_stats(id1), None);
+        assert!(alloc.region_stats(id3).is_some());
+
+        let mut alloc = Allocator::new();
+        let chunk = HEAP_SIZE / MAX_REGIONS;
+        for i in 0..MAX_REGIONS {
+            unsafe {
+                alloc.add_free_region(
+                    NonNull::new(slice_from_raw_parts_mut(
+                        heap1.wrapping_add(i * chunk),
+                        chunk,
+                    ))
+                    .unwrap(),
+                );
+            }
+        }
+        assert_eq!(
+            unsafe {
+                alloc.try_add_free_region(
+                    NonNull::new(slice_from_raw_parts_mut(heap2, chunk)).unwrap(),
+                )
+            },
+            Err(RegionError::TooManyRegions)
+        );
+    }
+
+    #[test]
+    fn from_slice() {
+        const HEAP_SIZE: usize = 1 << 6;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { &mut (*HEAP.get()).0 };
+        let start = heap.as_mut_ptr().addr();
+        let mut alloc = Allocator::from_slice(heap);
+        assert_eq!(free_list(&alloc), [(start, HEAP_SIZE)]);
+        assert!(unsafe { alloc.alloc(Layout::new::<[u8; HEAP_SIZE]>()) }.is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid free region")]
+    fn from_slice_misaligned() {
+        const HEAP_SIZE: usize = 1 << 6;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { &mut (*HEAP.get()).0 };
+        Allocator::from_slice(&mut heap[1..]);
+    }
+
+    #[test]
+    fn best_fit_ties() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::builder().strategy(Strategy::BestFit).build();
+        let l = Layout::new::<[u64; 4]>();
+        unsafe {
+            // equal blocks at 0x00, 0x40, 0x80 and 0xc0, the highest at the
+            // front of the list
+            for offset in [0x00, 0x40, 0x80, 0xc0] {
+                alloc.add_free_region(
+                    NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(offset), 32)).unwrap(),
+                );
+            }
+            let starts: Vec<_> = (0..4)
+                .map(|_| alloc.alloc(l).unwrap().as_mut_ptr().addr() - heap.addr())
+                .collect();
+            assert_eq!(starts, [0x00, 0x40, 0x80, 0xc0]);
+        }
+    }
+
+    #[test]
+    fn empty_and_full() {
+        const HEAP_SIZE: usize = 1 << 6;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::coalescing();
+        assert!(alloc.is_empty() && alloc.is_full());
+        let l = Layout::new::<[u64; 4]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            assert!(alloc.is_empty() && !alloc.is_full());
+            let p1 = alloc.alloc(l).unwrap();
+            assert!(!alloc.is_empty() && !alloc.is_full());
+            assert_eq!((alloc.used_bytes(), alloc.largest_free_block()), (32, 32));
+            let p2 = alloc.alloc(l).unwrap();
+            assert!(!alloc.is_empty() && alloc.is_full());
+            alloc.dealloc(p1.as_mut_ptr(), l);
+            alloc.dealloc(p2.as_mut_ptr(), l);
+            assert!(alloc.is_empty() && !alloc.is_full());
+            assert_eq!(alloc.largest_free_block(), HEAP_SIZE);
+        }
+    }
+
+    #[test]
+    fn realloc_aligned() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let (old, new) = (
+            Layout::from_size_align(32, 8).unwrap(),
+            Layout::from_size_align(32, 64).unwrap(),
+        );
+        unsafe {
+            // leave the buffer 8-aligned but not 64-aligned
+            let start = heap.wrapping_add(64 - heap.addr() % 64 + 8);
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(start, 160)).unwrap());
+            let p = alloc.alloc(old).unwrap().as_mut_ptr();
+            assert_ne!(p.addr() % 64, 0);
+            p.write_bytes(0xab, 32);
+            let moved = alloc.realloc_aligned(p, old, new).unwrap();
+            assert_eq!(moved.as_mut_ptr().addr() % 64, 0);
+            assert!(moved.as_ref()[..32].iter().all(|&b| b == 0xab));
+        }
+    }
+
+    #[test]
+    fn coalesce_to_regions() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP1: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        static HEAP2: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap1 = unsafe { addr_of_mut!((*HEAP1.get()).0) }.cast::<u8>();
+        let heap2 = unsafe { addr_of_mut!((*HEAP2.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 4]>();
+        unsafe {
+            alloc
+                .add_free_region(NonNull::new(slice_from_raw_parts_mut(heap1, HEAP_SIZE)).unwrap());
+            alloc
+                .add_free_region(NonNull::new(slice_from_raw_parts_mut(heap2, HEAP_SIZE)).unwrap());
+            let allocs: Vec<_> = (0..12).map(|_| alloc.alloc(l).unwrap()).collect();
+            for p in allocs {
+                alloc.dealloc(p.as_mut_ptr(), l);
+            }
+        }
+        assert!(alloc.free_block_count() > alloc.region_count());
+        alloc.coalesce_to_regions();
+        assert_eq!(alloc.free_block_count(), alloc.region_count());
+        assert_eq!(
+            free_list(&alloc),
+            [(heap2.addr(), HEAP_SIZE), (heap1.addr(), HEAP_SIZE)]
+        );
+        assert!(unsafe { alloc.alloc(Layout::new::<[u8; HEAP_SIZE]>()) }.is_some());
+    }
+
+    #[test]
+    fn leading_gap() {
+        const HEAP_SIZE: usize = 1 << 9;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let aligned = heap.wrapping_add(64 - heap.addr() % 64);
+        let l = Layout::from_size_align(32, 64).unwrap();
+        // a gap of 56 bytes is returned as it is, while one of 8 bytes cannot
+        // hold a node and grows by another 64
+        for (lead, gap) in [(56, 56), (8, 72)] {
+            let start = aligned.wrapping_add(64 - lead);
+            let mut alloc = Allocator::new();
+            unsafe {
+                alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(start, 256)).unwrap());
+                let p = alloc.alloc(l).unwrap();
+                assert_eq!(p.as_mut_ptr().addr() % 64, 0);
+                assert_eq!(p.as_mut_ptr().addr() - start.addr(), gap);
+            }
+            assert!(free_list(&alloc).contains(&(start.addr(), gap)));
+            assert_eq!(alloc.free_bytes(), 256 - 64);
+        }
+    }
+
+    #[test]
+    fn allocation_count() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::builder().max_allocations(2).build();
+        assert_eq!(alloc.max_allocations(), Some(2));
+        let l = Layout::new::<u64>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let p1 = alloc.alloc(l).unwrap();
+            let p2 = alloc.alloc(l).unwrap();
+            assert_eq!(alloc.allocation_count(), 2);
+            assert_eq!(alloc.try_alloc(l), Err(AllocError::TooManyAllocations));
+            alloc.dealloc(p1.as_mut_ptr(), l);
+            assert_eq!(alloc.allocation_count(), 1);
+            let p3 = alloc.alloc(l).unwrap();
+            alloc.dealloc(p2.as_mut_ptr(), l);
+            alloc.dealloc_unchecked(p3.as_mut_ptr(), l);
+        }
+        assert_eq!(alloc.allocation_count(), 0);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn alloc_traced() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 2]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+        }
+        let freed = unsafe { alloc.alloc_traced(l) }.unwrap();
+        let (leaked, line) = (unsafe { alloc.alloc_traced(l) }.unwrap(), line!());
+        unsafe { alloc.dealloc_traced(freed.as_mut_ptr(), l) };
+
+        let mut out = String::new();
+        alloc.report_leaks(&mut out).unwrap();
+        let expected = format!(
+            "leaked {:#x} 16 at {}:{line}:",
+            leaked.as_mut_ptr().addr(),
+            file!()
+        );
+        assert!(out.starts_with(&expected), "{out}");
+        assert_eq!(out.lines().count(), 1);
+    }
+
+    #[test]
+    fn region_boundaries() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::coalescing();
+        let l = Layout::new::<[u64; 4]>();
+        // region A is 0..64 and region B 64..128, so A's last block and B's
+        // first are adjacent
+        let (a, b) = unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, 64)).unwrap());
+            alloc.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(64), 64)).unwrap(),
+            );
+            let allocs: Vec<_> = (0..4)
+                .map(|_| alloc.alloc(l).unwrap().as_mut_ptr())
+                .collect();
+            let a = *allocs
+                .iter()
+                .find(|p| p.addr() == heap.addr() + 32)
+                .unwrap();
+            let b = *allocs
+                .iter()
+                .find(|p| p.addr() == heap.addr() + 64)
+                .unwrap();
+            (a, b)
+        };
+        unsafe {
+            alloc.dealloc(a, l);
+            assert!(!alloc.grow_in_place(a, l, 64));
+            alloc.dealloc(b, l);
+        }
+        let mut free = free_list(&alloc);
+        free.sort();
+        assert_eq!(free, [(a.addr(), 32), (b.addr(), 32)]);
+        alloc.merge_all();
+        assert_eq!(alloc.free_block_count(), 2);
+    }
+
+    #[test]
+    fn recent_first() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let mut alloc = Allocator::builder()
+            .coalesce(true)
+            .strategy(Strategy::RecentFirst)
+            .build();
+        let l = Layout::new::<[u64; 4]>();
+        unsafe {
+            alloc.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+            let ptrs: Vec<_> = (0..6)
+                .map(|_| alloc.alloc(l).unwrap().as_mut_ptr())
+                .collect();
+            alloc.dealloc(ptrs[2], l);
+            assert_eq!(alloc.alloc(l).unwrap().as_mut_ptr(), ptrs[2]);
+
+            // freeing ptrs[4] merges it into the free region at ptrs[3], which
+            // stays behind ptrs[1] in the list, so first fit would pick ptrs[1]
+            alloc.dealloc(ptrs[3], l);
+            alloc.dealloc(ptrs[1], l);
+            alloc.dealloc(ptrs[4], l);
+            assert_eq!(free_list(&alloc)[0].0, ptrs[1].addr());
+            assert_eq!(alloc.alloc(l).unwrap().as_mut_ptr(), ptrs[3]);
+        }
+    }
+
+    #[test]
+    fn alloc_within() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let max_addr = heap.addr() + 64;
+        let l = Layout::new::<[u64; 6]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let p = alloc.alloc_within(l, max_addr).unwrap();
+            assert_eq!(p.as_mut_ptr(), heap);
+            assert_eq!(free_list(&alloc), [(heap.addr() + 48, 80)]);
+            // the rest below max_addr is too small
+            assert!(alloc.alloc_within(l, max_addr).is_none());
+            assert!(alloc.alloc(l).is_some());
+        }
+    }
+
+    #[test]
+    fn overflowing_node_end() {
+        const HEAP_SIZE: usize = 1 << 6;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+        }
+        // the node claims to run past the end of the address space, so its
+        // end cannot be computed and the region is skipped
+        corrupt::set_size(&mut alloc, 0, usize::MAX - heap.addr() + 2 * HEAP_SIZE);
+        let l = Layout::from_size_align(4 * HEAP_SIZE, 8).unwrap();
+        assert!(unsafe { alloc.alloc(l) }.is_none());
+    }
+
+    #[test]
+    fn absorb() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let (mut a, mut b) = (Allocator::new(), Allocator::new());
+        let l = Layout::new::<[u64; 8]>();
+        unsafe {
+            a.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, 64)).unwrap());
+            b.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(64), 64)).unwrap(),
+            );
+            let p = a.alloc(l).unwrap();
+            assert!(a.alloc(l).is_none());
+
+            a.absorb(&mut b);
+            assert_eq!((b.region_count(), b.free_bytes()), (0, 0));
+            assert_eq!((a.region_count(), a.free_bytes()), (2, 64));
+            let q = a.alloc(l).unwrap();
+            assert_eq!(q.as_mut_ptr(), heap.wrapping_add(64));
+            a.dealloc(p.as_mut_ptr(), l);
+            a.dealloc(q.as_mut_ptr(), l);
+        }
+        assert!(a.is_empty());
+        assert_eq!(a.used_bytes(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "absorbing an allocator with live allocations")]
+    fn absorb_live() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let (mut a, mut b) = (Allocator::new(), Allocator::new());
+        unsafe {
+            a.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, 64)).unwrap());
+            b.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(64), 64)).unwrap(),
+            );
+            b.alloc(Layout::new::<u64>()).unwrap();
+            a.absorb(&mut b);
+        }
+    }
+
+    #[test]
+    fn alloc_reporting() {
+        const HEAP_SIZE: usize = 1 << 6;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 4]>();
+        unsafe {
+            alloc.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+            // the rest of the region is split off
+            let (p, split_off) = alloc.alloc_reporting(l).unwrap();
+            assert_eq!((split_off, alloc.free_block_count()), (1, 1));
+            // and then fits exactly
+            let (q, split_off) = alloc.alloc_reporting(l).unwrap();
+            assert_eq!((split_off, alloc.free_block_count()), (0, 0));
+            alloc.dealloc(p.as_mut_ptr(), l);
+            alloc.dealloc(q.as_mut_ptr(), l);
+        }
+    }
+
+    #[cfg(feature = "bump")]
+    #[test]
+    fn into_bump() {
+        const HEAP_SIZE: usize = 1 << 6;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 2]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let ptrs: Vec<_> = (0..4)
+                .map(|_| alloc.alloc(l).unwrap().as_mut_ptr())
+                .collect();
+            for &p in ptrs.iter().rev() {
+                alloc.dealloc(p, l);
+            }
+        }
+        assert_eq!(alloc.free_block_count(), 4);
+        let mut bump = alloc.into_bump().unwrap();
+        assert_eq!(bump.region_range(), heap.addr()..heap.addr() + HEAP_SIZE);
+        assert!(bump.as_bytes().is_none());
+        unsafe {
+            for i in 0..8 {
+                let p = bump.alloc(Layout::new::<u64>()).unwrap();
+                assert_eq!(p.as_mut_ptr(), heap.wrapping_add(8 * i));
+            }
+        }
+        assert!(bump.is_full());
+    }
+
+    #[test]
+    fn overaligned_small_layout() {
+        #[repr(align(64))]
+        struct MemPool64<const N: usize>([u8; N]);
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool64<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool64([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let l = Layout::from_size_align(8, 64).unwrap();
+        assert_eq!(Allocator::overhead_for(l), 56);
+
+        let mut alloc = Allocator::new();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let p = alloc.alloc(l).unwrap();
+            assert_eq!((p.as_mut_ptr(), p.len()), (heap, 64));
+            // the rest after the padding stays free
+            assert_eq!(free_list(&alloc), [(heap.addr() + 64, HEAP_SIZE - 64)]);
+            alloc.dealloc(p.as_mut_ptr(), l);
+        }
+        assert_eq!(alloc.free_bytes(), HEAP_SIZE);
+    }
+
+    #[test]
+    fn retain_free_blocks() {
+        const HEAP_SIZE: usize = 1 << 10;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let mut offset = 0;
+        for size in [32, 256, 64, 128, 16] {
+            unsafe {
+                alloc.add_free_region(
+                    NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(offset), size))
+                        .unwrap(),
+                );
+            }
+            offset += size;
+        }
+        assert_eq!(alloc.retain_free_blocks(|_, size| size >= 128), 3);
+        let mut free = free_list(&alloc);
+        free.sort();
+        assert_eq!(free, [(heap.addr() + 32, 256), (heap.addr() + 352, 128)]);
+        assert_eq!(alloc.retain_free_blocks(|_, _| true), 0);
+    }
+
+    #[test]
+    fn alloc_no_cross() {
+        #[repr(align(4096))]
+        struct Pages<const N: usize>([u8; N]);
+        const HEAP_SIZE: usize = 4 * PAGE_SIZE;
+        static HEAP: SyncUnsafeCell<Pages<HEAP_SIZE>> = SyncUnsafeCell::new(Pages([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let l = Layout::from_size_align(1024, 8).unwrap();
+        unsafe {
+            // a free region starting 3.5 KiB into the first 4 KiB window
+            let start = heap.wrapping_add(3584);
+            alloc.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(start, HEAP_SIZE - 3584)).unwrap(),
+            );
+            let p = alloc.alloc_no_cross(l, 4096).unwrap();
+            assert_eq!(p.as_mut_ptr(), heap.wrapping_add(4096));
+            // the skipped bytes are still free
+            let mut free = free_list(&alloc);
+            free.sort();
+            assert_eq!(free[0], (start.addr(), 512));
+            // fits in the window without moving
+            let q = alloc.alloc_no_cross(l, 4096).unwrap();
+            assert_eq!(q.as_mut_ptr(), heap.wrapping_add(5120));
+            assert!(alloc.alloc_no_cross(l, 512).is_none());
+        }
+    }
+
+    #[test]
+    fn alloc_or_grow() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 8]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, 64)).unwrap());
+            let p = alloc.alloc_or_grow(l, |_| panic!("grown")).unwrap();
+            let mut asked = None;
+            let q = alloc
+                .alloc_or_grow(l, |needed| {
+                    asked = Some(needed);
+                    NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(64), needed))
+                })
+                .unwrap();
+            assert_eq!(asked, Some(64));
+            assert_eq!(q.as_mut_ptr(), heap.wrapping_add(64));
+            assert_eq!(alloc.region_count(), 2);
+            // grow gets one chance
+            assert_eq!(
+                alloc.alloc_or_grow(l, |_| None),
+                Err(GrowError::Alloc(AllocError::OutOfMemory))
+            );
+            alloc.dealloc(p.as_mut_ptr(), l);
+            alloc.dealloc(q.as_mut_ptr(), l);
+        }
+
+        // more memory would not help an allocation over the limit
+        let mut alloc = Allocator::builder().max_alloc(32).build();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, 32)).unwrap());
+            assert_eq!(
+                alloc.alloc_or_grow(l, |_| panic!("grown")),
+                Err(GrowError::Alloc(AllocError::ExceedsLimit))
+            );
+        }
+
+        // a region that cannot be added is handed back
+        let mut alloc = Allocator::new();
+        let misaligned = NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(1), 96)).unwrap();
+        unsafe {
+            assert_eq!(
+                alloc.alloc_or_grow(l, |_| Some(misaligned)),
+                Err(GrowError::Rejected(misaligned, RegionError::Misaligned))
+            );
+        }
+        assert_eq!(alloc.region_count(), 0);
+    }
+
+    #[test]
+    fn whole_region() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+        }
+        let l = Layout::new::<[u8; HEAP_SIZE]>();
+        unsafe {
+            assert!(alloc.alloc(Layout::new::<[u8; HEAP_SIZE + 1]>()).is_none());
+            for _ in 0..2 {
+                let p = alloc.alloc(l).unwrap();
+                assert_eq!((p.as_mut_ptr(), p.len()), (heap, HEAP_SIZE));
+                assert_eq!(alloc.free_block_count(), 0);
+                assert!(alloc.alloc(Layout::new::<u8>()).is_none());
+                alloc.dealloc(p.as_mut_ptr(), l);
+                assert_eq!(free_list(&alloc), [(heap.addr(), HEAP_SIZE)]);
+            }
+        }
+        assert!(alloc.is_empty());
+    }
+
+    #[cfg(feature = "profiling")]
+    #[test]
+    fn realloc_tagged() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let tagged = |alloc: &Allocator| {
+            let mut out = String::new();
+            alloc.dump(&mut out).unwrap();
+            out.lines()
+                .filter(|line| line.starts_with("tagged"))
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        let l = Layout::new::<u64>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let p = alloc.alloc_tagged(l, 9).unwrap().as_mut_ptr();
+            p.cast::<u64>().write(0x1234);
+
+            // nothing follows it yet, so it grows in place
+            let p = alloc.realloc_tagged(p, l, 16).unwrap().as_mut_ptr();
+            assert_eq!(tagged(&alloc), [format!("tagged {:#x} 16 9", p.addr())]);
+
+            // something right after it, so that it has to move to grow
+            let blocker = alloc.alloc(l).unwrap().as_mut_ptr();
+            let l = Layout::from_size_align(16, 8).unwrap();
+            let q = alloc.realloc_tagged(p, l, 64).unwrap().as_mut_ptr();
+            assert_ne!(q, p);
+            assert_eq!(q.cast::<u64>().read(), 0x1234);
+            assert_eq!(tagged(&alloc), [format!("tagged {:#x} 64 9", q.addr())]);
+
+            alloc.dealloc_tagged(q, Layout::from_size_align(64, 8).unwrap());
+            alloc.dealloc(blocker, l);
+        }
+        assert!(tagged(&alloc).is_empty());
+        assert!(alloc.is_empty());
+    }
+
+    #[test]
+    fn free_list_checksum() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::coalescing();
+        let empty = alloc.free_list_checksum();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+        }
+        let fresh = alloc.free_list_checksum();
+        assert_ne!(fresh, empty);
+        // queries and failed requests leave the free list alone
+        alloc.verify().unwrap();
+        assert_eq!(alloc.free_bytes(), HEAP_SIZE);
+        assert!(unsafe { alloc.alloc(Layout::new::<[u8; 2 * HEAP_SIZE]>()) }.is_none());
+        assert_eq!(alloc.free_list_checksum(), fresh);
+
+        let l = Layout::new::<[u64; 4]>();
+        let p = unsafe { alloc.alloc(l) }.unwrap();
+        let allocated = alloc.free_list_checksum();
+        assert_ne!(allocated, fresh);
+        assert_eq!(alloc.free_list_checksum(), allocated);
+        unsafe { alloc.dealloc(p.as_mut_ptr(), l) };
+        // back to the same single region
+        assert_eq!(alloc.free_list_checksum(), fresh);
+    }
+
+    #[test]
+    fn zero_size() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::coalescing();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+        }
+        let l = Layout::from_size_align(0, 4096).unwrap();
+        unsafe {
+            let p = alloc.alloc(l).unwrap();
+            assert_eq!((p.as_mut_ptr().addr(), p.len()), (4096, 0));
+            assert!(p.as_mut_ptr().is_aligned_to(4096));
+            assert_eq!(alloc.allocation_count(), 0);
+            assert_eq!(free_list(&alloc), [(heap.addr(), HEAP_SIZE)]);
+            assert!(!alloc.grow_in_place(p.as_mut_ptr(), l, 8));
+            alloc.dealloc(p.as_mut_ptr(), l);
+        }
+        assert!(alloc.is_empty());
+        assert_eq!(free_list(&alloc), [(heap.addr(), HEAP_SIZE)]);
+    }
+
+    #[cfg(feature = "bump")]
+    #[test]
+    fn compact() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 4]>();
+        let mut moves = Vec::new();
+        let mut bump = unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let a = alloc.alloc(l).unwrap().as_mut_ptr();
+            let hole = alloc.alloc(l).unwrap().as_mut_ptr();
+            let b = alloc.alloc(l).unwrap().as_mut_ptr();
+            assert_eq!([a, hole, b], [0, 32, 64].map(|i| heap.wrapping_add(i)));
+            b.cast::<u64>().write(0xb);
+            alloc.dealloc(hole, l);
+
+            let bump = alloc.compact(|old, new, size| {
+                ptr::copy(old, new, size);
+                moves.push((old, new, size));
+            });
+            // a is already in place
+            assert_eq!(moves, [(b, hole, 32)]);
+            assert_eq!(hole.cast::<u64>().read(), 0xb);
+            assert_eq!(alloc.free_block_count(), 0);
+            alloc.dealloc(a, l);
+            alloc.dealloc(hole, l);
+            bump
+        };
+        assert!(alloc.is_empty());
+        assert_eq!(alloc.free_bytes(), 64);
+        assert_eq!(alloc.used_bytes(), 0);
+        assert_eq!(
+            bump.region_range(),
+            heap.addr() + 64..heap.addr() + HEAP_SIZE
+        );
+        assert!(bump.as_bytes().is_none());
+        let p = unsafe { bump.alloc(Layout::new::<u64>()) }.unwrap();
+        assert_eq!(p.as_mut_ptr(), heap.wrapping_add(64));
+    }
+
+    #[cfg(all(feature = "bump", feature = "profiling"))]
+    #[test]
+    fn compact_tagged() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<u64>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let hole = alloc.alloc(l).unwrap();
+            let p = alloc.alloc_tagged(l, 3).unwrap();
+            alloc.dealloc(hole.as_mut_ptr(), l);
+            // the header of `p` would be moved out from under the tag list
+            let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                alloc.compact(|old, new, size| ptr::copy(old, new, size))
+            }));
+            assert!(result.is_err());
+            let mut out = String::new();
+            alloc.dump(&mut out).unwrap();
+            assert!(out.contains(&format!("tagged {:#x} 8 3", p.as_mut_ptr().addr())));
+            alloc.dealloc_tagged(p.as_mut_ptr(), l);
+        }
+        assert!(alloc.is_empty());
+    }
+
+    #[test]
+    fn split_free_block() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::coalescing();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            assert!(alloc.split_free_block(heap.addr() + 8, 64, 128).is_none());
+            assert!(alloc.split_free_block(heap.addr(), 128, 136).is_none());
+            // 8 bytes left over could not hold a node
+            assert!(alloc.split_free_block(heap.addr(), 120, 128).is_none());
+            assert!(alloc.split_free_block(heap.addr(), 0, 128).is_none());
+            assert!(alloc.split_free_block(heap.addr(), 64, 0).is_none());
+            assert_eq!(free_list(&alloc), [(heap.addr(), HEAP_SIZE)]);
+
+            let (a, b) = alloc.split_free_block(heap.addr(), 64, 128).unwrap();
+            assert_eq!((a.as_mut_ptr(), a.len()), (heap, 64));
+            assert_eq!((b.as_mut_ptr(), b.len()), (heap.wrapping_add(64), 128));
+            assert_eq!(free_list(&alloc), [(heap.addr() + 192, 64)]);
+            assert_eq!(alloc.allocation_count(), 2);
+
+            alloc.dealloc(a.as_mut_ptr(), Layout::from_size_align(64, 8).unwrap());
+            alloc.dealloc(b.as_mut_ptr(), Layout::from_size_align(128, 8).unwrap());
+        }
+        assert!(alloc.is_empty());
+        assert_eq!(free_list(&alloc), [(heap.addr(), HEAP_SIZE)]);
+
+        // the pieces are held to the same limits as any allocation
+        let mut alloc = Allocator::builder().max_alloc(64).build();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            assert!(alloc.split_free_block(heap.addr(), 64, 128).is_none());
+            assert!(alloc.split_free_block(heap.addr(), 64, 64).is_some());
+        }
+    }
+
+    #[test]
+    fn forget_region() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP1: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        static HEAP2: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let [heap1, heap2] =
+            [HEAP1.get(), HEAP2.get()].map(|heap| unsafe { addr_of_mut!((*heap).0) }.cast::<u8>());
+        let region2 = NonNull::new(slice_from_raw_parts_mut(heap2, HEAP_SIZE)).unwrap();
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 4]>();
+        unsafe {
+            let id1 = alloc
+                .add_free_region(NonNull::new(slice_from_raw_parts_mut(heap1, HEAP_SIZE)).unwrap());
+            let id2 = alloc.add_free_region(region2);
+            assert_ne!(id1, id2);
+            // split the second region into several free blocks
+            let p = alloc.alloc(l).unwrap().as_mut_ptr();
+            assert_eq!(p, heap2);
+            alloc.dealloc(p, l);
+            assert_eq!(alloc.free_block_count(), 3);
+
+            assert_eq!(alloc.forget_region(id2), Some(region2));
+            assert_eq!(alloc.forget_region(id2), None);
+            let past_end = RegionId {
+                slot: MAX_REGIONS,
+                generation: 0,
+            };
+            assert_eq!(alloc.forget_region(past_end), None);
+            assert_eq!(free_list(&alloc), [(heap1.addr(), HEAP_SIZE)]);
+            assert_eq!(alloc.region_count(), 1);
+            assert!(alloc.is_empty());
+
+            // the next region takes the slot, but not the id, so the stale id
+            // cannot forget it
+            let id3 = alloc.add_free_region(region2);
+            assert_ne!(id3, id2);
+            assert_eq!(alloc.forget_region(id2), None);
+            assert_eq!(alloc.free_bytes(), 2 * HEAP_SIZE);
+        }
+    }
+
+    #[test]
+    fn layout_overflow() {
+        const HEAP_SIZE: usize = 1 << 6;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        // a valid layout, but padding it to a whole node takes it past
+        // isize::MAX
+        let l = Layout::from_size_align(isize::MAX.unsigned_abs() - 2, 1).unwrap();
+        assert_eq!(Allocator::adjust(l), None);
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            assert_eq!(alloc.try_alloc(l), Err(AllocError::LayoutOverflow));
+            assert!(alloc.alloc(l).is_none());
+            assert_eq!(
+                alloc.alloc_or_grow(l, |_| panic!("grown")),
+                Err(GrowError::Alloc(AllocError::LayoutOverflow))
+            );
+        }
+        assert_eq!(free_list(&alloc), [(heap.addr(), HEAP_SIZE)]);
+    }
+
+    #[test]
+    fn regions() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP1: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        static HEAP2: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap1 = unsafe { addr_of_mut!((*HEAP1.get()).0) }.cast::<u8>();
+        let heap2 = unsafe { addr_of_mut!((*HEAP2.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 4]>();
+        let (id1, id2) = unsafe {
+            let id1 = alloc
+                .add_free_region(NonNull::new(slice_from_raw_parts_mut(heap1, HEAP_SIZE)).unwrap());
+            let a = alloc.alloc(l).unwrap();
+            // only half of the second pool
+            let id2 = alloc.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(heap2, HEAP_SIZE / 2)).unwrap(),
+            );
+            let b = alloc.alloc(l).unwrap();
+            let c = alloc.alloc(l).unwrap();
+            alloc.dealloc(b.as_mut_ptr(), l);
+            assert_eq!(
+                [a, c].map(|p| p.as_mut_ptr()),
+                [heap1, heap2.wrapping_add(32)]
+            );
+            (id1, id2)
+        };
+        let regions: Vec<_> = alloc.regions().collect();
+        assert_eq!(
+            regions,
+            [
+                RegionInfo {
+                    id: id1,
+                    start: heap1.addr(),
+                    size: HEAP_SIZE,
+                    free_bytes: HEAP_SIZE - 32,
+                    free_blocks: 1,
+                },
+                RegionInfo {
+                    id: id2,
+                    start: heap2.addr(),
+                    size: HEAP_SIZE / 2,
+                    free_bytes: HEAP_SIZE / 2 - 32,
+                    free_blocks: 2,
+                },
+            ]
+        );
+        assert_eq!(
+            regions
+                .iter()
+                .map(|region| region.free_bytes)
+                .sum::<usize>(),
+            alloc.free_bytes()
+        );
+    }
+
+    #[test]
+    fn min_useful_remainder() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let l = Layout::new::<[u64; 12]>();
+        for (min, attached) in [(mem::size_of::<Node>(), 0), (64, 32)] {
+            let mut alloc = Allocator::builder().min_useful_remainder(min).build();
+            assert_eq!(alloc.min_useful_remainder(), min);
+            unsafe {
+                alloc.add_free_region(
+                    NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap(),
+                );
+                // 32 bytes are left over
+                let p = alloc.alloc(l).unwrap();
+                assert_eq!((p.as_mut_ptr(), p.len()), (heap, l.size()));
+                assert_eq!(alloc.attached_bytes(), attached);
+                assert_eq!(alloc.free_block_count(), usize::from(attached == 0));
+                // the layout it was made with gives back what was attached
+                alloc.dealloc(p.as_mut_ptr(), l);
+            }
+            assert!(alloc.is_empty());
+            assert_eq!(alloc.attached_bytes(), 0);
+        }
+
+        // a sliver too small for a node is left attached as well
+        let mut alloc = Allocator::builder().min_useful_remainder(64).build();
+        let l = Layout::new::<[u64; 15]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let p = alloc.alloc(l).unwrap();
+            assert_eq!(alloc.attached_bytes(), 8);
+            alloc.dealloc(p.as_mut_ptr(), l);
+        }
+        assert_eq!(free_list(&alloc), [(heap.addr(), HEAP_SIZE)]);
+    }
+
+    #[test]
+    fn realloc_same_block() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::coalescing();
+        let (l10, l14) = (Layout::new::<[u8; 10]>(), Layout::new::<[u8; 14]>());
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let p = alloc.alloc(l10).unwrap().as_mut_ptr();
+            let checksum = alloc.free_list_checksum();
+            let q = alloc.realloc(p, l10, 14).unwrap();
+            assert_eq!((q.as_mut_ptr(), q.len()), (p, 16));
+            assert_eq!(alloc.free_list_checksum(), checksum);
+            let q = alloc.realloc(p, l14, 10).unwrap();
+            assert_eq!((q.as_mut_ptr(), q.len()), (p, 16));
+            assert_eq!(alloc.free_list_checksum(), checksum);
+
+            let z = alloc.alloc(Layout::new::<()>()).unwrap();
+            let z = alloc
+                .realloc(z.as_mut_ptr(), Layout::new::<()>(), 0)
+                .unwrap();
+            assert_eq!(z.len(), 0);
+            alloc.dealloc(p, l10);
+        }
+        assert!(alloc.is_empty());
+    }
+
+    #[test]
+    fn small_align() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let (byte, bytes) = (Layout::new::<u8>(), Layout::new::<[u8; 17]>());
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            // a byte takes up a whole node whatever its alignment
+            let ptrs: Vec<_> = (0..4).map(|_| alloc.alloc(byte).unwrap()).collect();
+            assert_eq!(alloc.used_bytes(), 4 * mem::size_of::<Node>());
+            // and odd sizes are only padded up to the next node-aligned
+            // address, the least that leaves the rest usable as a free block
+            let p = alloc.alloc(bytes).unwrap();
+            assert_eq!(p.len(), 24);
+            assert_eq!(alloc.used_bytes(), 4 * mem::size_of::<Node>() + 24);
+            alloc.dealloc(p.as_mut_ptr(), bytes);
+            for p in ptrs {
+                alloc.dealloc(p.as_mut_ptr(), byte);
+            }
+        }
+        assert_eq!(alloc.used_bytes(), 0);
+    }
+
+    #[test]
+    fn check_poison() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::builder().coalesce(true).poison(0xde).build();
+        let l = Layout::new::<[u8; 32]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let a = alloc.alloc(l).unwrap();
+            let b = alloc.alloc(l).unwrap();
+            a.as_mut_ptr().write_bytes(0, 32);
+            b.as_mut_ptr().write_bytes(0, 32);
+            // merged with the rest of the region, whose header is poisoned
+            alloc.dealloc(b.as_mut_ptr(), l);
+            let b = alloc.alloc(Layout::new::<[u8; 64]>()).unwrap();
+            alloc.dealloc(b.as_mut_ptr(), Layout::new::<[u8; 64]>());
+
+            alloc.dealloc(a.as_mut_ptr(), l);
+            // a write past the header of the free region it is now part of
+            a.as_mut_ptr().wrapping_add(mem::size_of::<Node>()).write(0);
+            let result = panic::catch_unwind(panic::AssertUnwindSafe(|| alloc.alloc(l)));
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn grow_leaving_sliver() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::builder().min_useful_remainder(32).build();
+        let l = Layout::new::<[u8; 32]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, 192)).unwrap());
+            let a = alloc.alloc(l).unwrap();
+            let b = alloc.alloc(l).unwrap();
+            let c = alloc.alloc(l).unwrap();
+            alloc.dealloc(b.as_mut_ptr(), l);
+            // growing into b would leave a 16 byte sliver of it
+            assert!(!alloc.grow_in_place(a.as_mut_ptr(), l, 48));
+            let moved = alloc.realloc(a.as_mut_ptr(), l, 48).unwrap();
+            assert_eq!(moved.as_mut_ptr(), heap.wrapping_add(96));
+
+            // while taking up all of the free region after it is fine
+            let l = Layout::new::<[u8; 48]>();
+            assert!(alloc.grow_in_place(moved.as_mut_ptr(), l, 96));
+            alloc.dealloc(moved.as_mut_ptr(), Layout::new::<[u8; 96]>());
+            alloc.dealloc(c.as_mut_ptr(), Layout::new::<[u8; 32]>());
+        }
+        assert_eq!(alloc.free_bytes(), 192);
+    }
+
+    #[test]
+    fn max_search_steps() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::builder().max_search_steps(4).build();
+        assert_eq!(alloc.max_search_steps(), Some(4));
+        let (small, large) = (Layout::new::<[u8; 16]>(), Layout::new::<[u8; 32]>());
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            // every other block freed leaves six free regions too small for a
+            // large allocation in front of the rest of the heap
+            let ptrs: Vec<_> = (0..12).map(|_| alloc.alloc(small).unwrap()).collect();
+            for p in ptrs.iter().step_by(2) {
+                alloc.dealloc(p.as_mut_ptr(), small);
+            }
+            assert_eq!(alloc.free_block_count(), 7);
+            assert_eq!(alloc.try_alloc(large), Err(AllocError::BudgetExceeded));
+            assert!(alloc.try_alloc(small).is_ok());
+
+            alloc.max_search_steps = None;
+            assert!(alloc.try_alloc(large).is_ok());
+        }
+    }
+
+    #[test]
+    fn add_zeroed_region() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::builder().coalesce(true).build();
+        let l = Layout::new::<[u8; 64]>();
+        let zeroed = || ZEROED_BYTES.with(Cell::get);
+        unsafe {
+            alloc.add_zeroed_region(
+                NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap(),
+            );
+            // only the node of the free region is zeroed
+            let before = zeroed();
+            let p = alloc.alloc_zeroed(l).unwrap();
+            assert_eq!(zeroed() - before, mem::size_of::<Node>());
+            assert!(p.as_ref().iter().all(|&b| b == 0));
+            p.as_mut_ptr().write_bytes(0xff, 64);
+            let q = alloc.alloc_zeroed(l).unwrap();
+            assert_eq!(zeroed() - before, 2 * mem::size_of::<Node>());
+            assert!(q.as_ref().iter().all(|&b| b == 0));
+
+            // whereas memory that has been handed out before is zeroed in full
+            alloc.dealloc(p.as_mut_ptr(), l);
+            let before = zeroed();
+            let p = alloc.alloc_zeroed(l).unwrap();
+            assert_eq!(zeroed() - before, 64);
+            assert!(p.as_ref().iter().all(|&b| b == 0));
+            alloc.dealloc(p.as_mut_ptr(), l);
+
+            // merging the rest of the region into a freed block zeroes its
+            // node, which is left behind in what is still pristine memory
+            alloc.dealloc(q.as_mut_ptr(), l);
+            assert_eq!(free_list(&alloc), [(heap.addr(), HEAP_SIZE)]);
+            let rest = &*ptr::slice_from_raw_parts(heap.wrapping_add(128), HEAP_SIZE - 128);
+            assert!(rest.iter().all(|&b| b == 0));
+        }
+    }
+
+    #[test]
+    fn status_of() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u8; 32]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let p = alloc.alloc(l).unwrap().as_mut_ptr();
+            assert_eq!(alloc.status_of(p), Some(BlockStatus::Allocated));
+            assert_eq!(
+                alloc.status_of(p.wrapping_add(31)),
+                Some(BlockStatus::Allocated)
+            );
+            assert_eq!(alloc.status_of(p.wrapping_add(32)), Some(BlockStatus::Free));
+            alloc.dealloc(p, l);
+            assert_eq!(alloc.status_of(p), Some(BlockStatus::Free));
+            assert_eq!(alloc.status_of(p.wrapping_add(31)), Some(BlockStatus::Free));
+        }
+        assert_eq!(alloc.status_of(heap.wrapping_add(HEAP_SIZE)), None);
+    }
+
+    #[test]
+    fn guard_bytes() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::builder().guard_bytes(4).build();
+        assert_eq!(alloc.guard_bytes(), 4);
+        let l = Layout::new::<[u8; 24]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let a = alloc.alloc(l).unwrap();
+            let b = alloc.alloc(l).unwrap();
+            assert_eq!(a.len(), 24);
+            // the fill rounded up to a word and the link to the next guard
+            assert_eq!(b.as_mut_ptr(), a.as_mut_ptr().wrapping_add(24 + 16));
+            assert_eq!(alloc.guard_total(), 32);
+            assert_eq!(alloc.used_bytes(), 48);
+            assert!(!alloc.grow_in_place(a.as_mut_ptr(), l, 32));
+            assert_eq!(alloc.verify(), Ok(()));
+
+            // one byte past the end of b lands in its guard
+            b.as_mut_ptr().wrapping_add(24).write(0);
+            assert_eq!(
+                alloc.verify(),
+                Err(VerifyError::GuardOverwritten(b.as_mut_ptr().addr() + 24))
+            );
+            b.as_mut_ptr().wrapping_add(24).write(0xfd);
+            alloc.dealloc(b.as_mut_ptr(), l);
+            alloc.dealloc(a.as_mut_ptr(), l);
+        }
+        assert_eq!(alloc.verify(), Ok(()));
+        assert_eq!((alloc.guard_total(), alloc.used_bytes()), (0, 0));
+    }
+
+    #[test]
+    fn grow_down() {
+        #[repr(align(64))]
+        struct MemPool64<const N: usize>([u8; N]);
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool64<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool64([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::builder().grow_down(true).build();
+        assert!(alloc.is_grow_down());
+        let l = Layout::new::<[u8; 32]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, 128)).unwrap());
+            alloc.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(128), 64)).unwrap(),
+            );
+            let ptrs: Vec<_> = (0..3)
+                .map(|_| alloc.alloc(l).unwrap().as_mut_ptr())
+                .collect();
+            // the top of the higher region first, then on down
+            let offsets: Vec<_> = ptrs.iter().map(|p| p.addr() - heap.addr()).collect();
+            assert_eq!(offsets, [160, 128, 96]);
+            assert_eq!(free_list(&alloc), [(heap.addr(), 96)]);
+
+            // an aligned allocation leaves the gap above it free
+            let p = alloc
+                .alloc(Layout::from_size_align(64, 64).unwrap())
+                .unwrap();
+            assert_eq!(p.as_mut_ptr(), heap);
+            assert_eq!(free_list(&alloc), [(heap.addr() + 64, 32)]);
+        }
+    }
+
+    #[test]
+    fn heap_bounds() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        static OTHER: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let [heap, other] =
+            [HEAP.get(), OTHER.get()].map(|heap| unsafe { addr_of_mut!((*heap).0) }.cast::<u8>());
+        let mut alloc = Allocator::new();
+        assert_eq!((alloc.heap_start(), alloc.heap_end()), (None, None));
+        let l = Layout::new::<u64>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            assert_eq!(alloc.heap_start(), Some(heap.addr()));
+            assert_eq!(alloc.heap_end(), Some(heap.addr() + HEAP_SIZE));
+            if cfg!(debug_assertions) {
+                let result =
+                    panic::catch_unwind(panic::AssertUnwindSafe(|| alloc.dealloc(other, l)));
+                assert!(result.is_err());
+            }
+
+            alloc
+                .add_free_region(NonNull::new(slice_from_raw_parts_mut(other, HEAP_SIZE)).unwrap());
+            assert_eq!((alloc.heap_start(), alloc.heap_end()), (None, None));
+        }
+    }
+
+    #[test]
+    fn max_free_blocks() {
+        #[repr(align(64))]
+        struct MemPool64<const N: usize>([u8; N]);
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool64<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool64([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::builder().max_free_blocks(1).build();
+        assert_eq!(alloc.max_free_blocks(), Some(1));
+        let (small, aligned) = (
+            Layout::new::<[u8; 16]>(),
+            Layout::from_size_align(64, 64).unwrap(),
+        );
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            // splitting off the rest leaves a single free region
+            let p = alloc.alloc(small).unwrap();
+            assert_eq!(alloc.free_block_count(), 1);
+            // whereas this would leave a gap in front as well
+            assert_eq!(
+                alloc.try_alloc(aligned),
+                Err(AllocError::FragmentationLimit)
+            );
+
+            // freeing can go past the limit, and then an exact fit still works
+            alloc.dealloc(p.as_mut_ptr(), small);
+            assert_eq!(alloc.free_block_count(), 2);
+            assert_eq!(alloc.alloc(small), Some(p));
+            assert!(alloc.alloc(Layout::new::<[u8; 32]>()).is_some());
+        }
+    }
+
+    #[test]
+    fn merge_all_stats() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 4]>();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let blocks = [(); HEAP_SIZE / 32].map(|_| alloc.alloc(l).unwrap());
+            // leave the fourth block live, splitting the heap in two runs
+            for (i, block) in blocks.iter().enumerate() {
+                if i != 3 {
+                    alloc.dealloc(block.as_mut_ptr(), l);
+                }
+            }
+        }
+        assert_eq!(
+            alloc.merge_all(),
+            DefragStats {
+                merges: 5,
+                before_largest: 32,
+                after_largest: 128,
+                before_blocks: 7,
+                after_blocks: 2,
+                more: false,
+            }
+        );
+        // nothing is left to merge
+        let stats = alloc.merge_all();
+        assert_eq!((stats.merges, stats.after_blocks), (0, 2));
+    }
+
+    #[test]
+    fn alloc_adjusted() {
+        const HEAP_SIZE: usize = 1 << 9;
+        static HEAPS: SyncUnsafeCell<[MemPool<HEAP_SIZE>; 2]> =
+            SyncUnsafeCell::new([MemPool([0; HEAP_SIZE]), MemPool([0; HEAP_SIZE])]);
+        let heaps = unsafe { &mut *HEAPS.get() };
+        let [mut plain, mut adjusted] = [(); 2].map(|_| Allocator::new());
+        let l = Layout::new::<[u8; 5]>();
+        let al = Allocator::adjust(l).unwrap();
+        assert_eq!(Allocator::adjust(al), Some(al));
+        unsafe {
+            for (alloc, heap) in [&mut plain, &mut adjusted].into_iter().zip(heaps) {
+                alloc.add_free_region(NonNull::from(&mut heap.0));
+            }
+            let offset = |alloc: &Allocator, p: NonNull<[u8]>| {
+                (p.addr().get() - alloc.heap_start().unwrap(), p.len())
+            };
+            let mut n = 0;
+            while let Some(p) = plain.alloc(l) {
+                let q = adjusted.alloc_adjusted(al).unwrap();
+                assert_eq!(offset(&plain, p), offset(&adjusted, q));
+                n += 1;
+            }
+            assert_eq!(n, HEAP_SIZE / al.size());
+            assert_eq!(adjusted.alloc_adjusted(al), None);
+        }
+        assert_eq!(plain.used_bytes(), adjusted.used_bytes());
+    }
+
+    #[test]
+    fn realloc_same_region() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP1: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        static HEAP2: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap1 = unsafe { addr_of_mut!((*HEAP1.get()).0) }.cast::<u8>();
+        let heap2 = unsafe { addr_of_mut!((*HEAP2.get()).0) }.cast::<u8>();
+        let region_of = |alloc: &Allocator, p: NonNull<[u8]>| {
+            alloc
+                .regions()
+                .find(|region| p.addr().get().wrapping_sub(region.start) < region.size)
+                .map(|region| region.id)
+        };
+        let mut alloc = Allocator::new();
+        let l = Layout::new::<[u64; 4]>();
+        unsafe {
+            let id1 = alloc
+                .add_free_region(NonNull::new(slice_from_raw_parts_mut(heap1, HEAP_SIZE)).unwrap());
+            let p = alloc.alloc(l).unwrap();
+            // keeps `p` from growing in place
+            let _q = alloc.alloc(l).unwrap();
+            let id2 = alloc
+                .add_free_region(NonNull::new(slice_from_raw_parts_mut(heap2, HEAP_SIZE)).unwrap());
+            // the new region is first in the free list, so a plain allocation
+            // would come from it
+            let probe = alloc.alloc(l).unwrap();
+            assert_eq!(region_of(&alloc, probe), Some(id2));
+            alloc.dealloc(probe.as_mut_ptr(), l);
+
+            p.as_mut_ptr().write_bytes(7, l.size());
+            let p = alloc.realloc(p.as_mut_ptr(), l, 64).unwrap();
+            assert_eq!(region_of(&alloc, p), Some(id1));
+            assert_eq!(p.as_mut_ptr(), heap1.wrapping_add(64));
+            assert_eq!(*p.as_mut_ptr().add(31), 7);
+
+            // too big for what is left of the first region
+            let p = alloc
+                .realloc(p.as_mut_ptr(), Layout::new::<[u64; 8]>(), 200)
+                .unwrap();
+            assert_eq!(region_of(&alloc, p), Some(id2));
+            assert_eq!(*p.as_mut_ptr().add(31), 7);
         }
     }
 }
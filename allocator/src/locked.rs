@@ -0,0 +1,492 @@
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cell::UnsafeCell,
+    hint,
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use super::Allocator;
+
+/// Wraps an allocator in a spinlock, so that it can be shared between threads
+/// and used as the global allocator.
+///
+/// Taking the lock is an `Acquire` and releasing it a `Release`, so everything
+/// done while the lock was last held happens-before the next critical
+/// section. In particular, whatever a thread wrote into a block before
+/// freeing it is visible to the thread that is handed that block next.
+pub struct Locked<A> {
+    locked: AtomicBool,
+    backoff: Backoff,
+    contended: AtomicU64,
+    allocs: AtomicU64,
+    frees: AtomicU64,
+    live_bytes: AtomicU64,
+    inner: UnsafeCell<A>,
+}
+
+/// Counts of what has gone through a [`Locked`] allocator, as read by
+/// [`Locked::stats`]. Resizing an allocation changes `live_bytes` only.
+///
+/// `live_bytes` counts the [usable size](Allocator::usable_size) of each
+/// allocation, which is what the inner allocator sets aside for it, so that
+/// freeing an allocation with any layout that fits it takes off as much as
+/// allocating it added.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub allocs: u64,
+    pub frees: u64,
+    pub live_bytes: u64,
+}
+
+/// How a thread waits between attempts to take a contended lock.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backoff {
+    /// Try again as soon as the lock looks free.
+    #[default]
+    Spin,
+    /// Spin for `2^n` spin-loop hints before looking at the lock again, with
+    /// `n` going up by one after every failed attempt until it reaches
+    /// `max_shift`, which is at most 31. Waiting threads then mostly leave
+    /// the lock's cache line alone.
+    Exponential { max_shift: u32 },
+}
+
+// SAFETY: the lock gives one thread at a time access to the inner allocator
+unsafe impl<A: Send> Sync for Locked<A> {}
+
+impl<A> Locked<A> {
+    pub const fn new(inner: A) -> Self {
+        Self::with_backoff(inner, Backoff::Spin)
+    }
+
+    pub const fn with_backoff(inner: A, backoff: Backoff) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            backoff,
+            contended: AtomicU64::new(0),
+            allocs: AtomicU64::new(0),
+            frees: AtomicU64::new(0),
+            live_bytes: AtomicU64::new(0),
+            inner: UnsafeCell::new(inner),
+        }
+    }
+
+    pub fn backoff(&self) -> Backoff {
+        self.backoff
+    }
+
+    /// Returns how many times taking the lock had to wait for it.
+    pub fn contended(&self) -> u64 {
+        self.contended.load(Ordering::Relaxed)
+    }
+
+    /// Returns the allocation counters, which are updated while the lock is
+    /// held but read without taking it, so that a monitor does not contend
+    /// with allocations. Each counter only moves forward, but the counters
+    /// are read one at a time, so they need not be from the same moment.
+    /// Only allocations made through [`GlobalAlloc`] and
+    /// [`core::alloc::Allocator`] are counted, not those made through a
+    /// [`Guard`].
+    pub fn stats(&self) -> Stats {
+        Stats {
+            allocs: self.allocs.load(Ordering::Relaxed),
+            frees: self.frees.load(Ordering::Relaxed),
+            live_bytes: self.live_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    fn count_alloc(&self, size: usize) {
+        self.allocs.fetch_add(1, Ordering::Relaxed);
+        self.live_bytes.fetch_add(bytes(size), Ordering::Relaxed);
+    }
+
+    fn count_dealloc(&self, size: usize) {
+        self.frees.fetch_add(1, Ordering::Relaxed);
+        self.live_bytes.fetch_sub(bytes(size), Ordering::Relaxed);
+    }
+
+    fn count_resize(&self, old_size: usize, new_size: usize) {
+        self.live_bytes
+            .fetch_sub(bytes(old_size), Ordering::Relaxed);
+        self.live_bytes
+            .fetch_add(bytes(new_size), Ordering::Relaxed);
+    }
+
+    /// Spins until the lock is free and takes it.
+    pub fn lock(&self) -> Guard<'_, A> {
+        let mut shift = 0;
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            if shift == 0 {
+                self.contended.fetch_add(1, Ordering::Relaxed);
+            }
+            if let Backoff::Exponential { max_shift } = self.backoff {
+                for _ in 0..1u32 << Ord::min(shift, 31) {
+                    hint::spin_loop();
+                }
+                shift = Ord::min(shift + 1, max_shift);
+            }
+            while self.locked.load(Ordering::Relaxed) {
+                hint::spin_loop();
+            }
+        }
+        Guard { lock: self }
+    }
+
+    pub fn into_inner(self) -> A {
+        self.inner.into_inner()
+    }
+}
+
+fn bytes(size: usize) -> u64 {
+    u64::try_from(size).unwrap_or(u64::MAX)
+}
+
+/// Exclusive access to the allocator in a [`Locked`], released on drop.
+pub struct Guard<'a, A> {
+    lock: &'a Locked<A>,
+}
+
+impl<A> Deref for Guard<'_, A> {
+    type Target = A;
+
+    fn deref(&self) -> &A {
+        unsafe { &*self.lock.inner.get() }
+    }
+}
+
+impl<A> DerefMut for Guard<'_, A> {
+    fn deref_mut(&mut self) -> &mut A {
+        unsafe { &mut *self.lock.inner.get() }
+    }
+}
+
+impl<A> Drop for Guard<'_, A> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
+
+unsafe impl<A: Allocator + Send> GlobalAlloc for Locked<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut inner = self.lock();
+        match unsafe { inner.alloc(layout) } {
+            Some(p) => {
+                self.count_alloc(inner.usable_size(layout));
+                p.as_mut_ptr()
+            }
+            None => ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut inner = self.lock();
+        let size = inner.usable_size(layout);
+        unsafe { inner.dealloc(ptr, layout) };
+        self.count_dealloc(size);
+    }
+}
+
+// The returned slices cover all of the memory the inner allocator set aside,
+// which can be more than was asked for, and resizing keeps the contents that
+// fit like `Allocator::realloc_aligned` does.
+unsafe impl<A: Allocator + Send> core::alloc::Allocator for Locked<A> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, core::alloc::AllocError> {
+        let mut inner = self.lock();
+        let p = unsafe { inner.alloc(layout) }.ok_or(core::alloc::AllocError)?;
+        self.count_alloc(inner.usable_size(layout));
+        Ok(p)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let mut inner = self.lock();
+        let size = inner.usable_size(layout);
+        unsafe { inner.dealloc(ptr.as_ptr(), layout) };
+        self.count_dealloc(size);
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, core::alloc::AllocError> {
+        let mut inner = self.lock();
+        let p = unsafe { inner.realloc_aligned(ptr.as_ptr(), old_layout, new_layout) }
+            .ok_or(core::alloc::AllocError)?;
+        self.count_resize(inner.usable_size(old_layout), inner.usable_size(new_layout));
+        Ok(p)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, core::alloc::AllocError> {
+        let mut inner = self.lock();
+        let p = unsafe { inner.realloc_aligned(ptr.as_ptr(), old_layout, new_layout) }
+            .ok_or(core::alloc::AllocError)?;
+        self.count_resize(inner.usable_size(old_layout), inner.usable_size(new_layout));
+        Ok(p)
+    }
+}
+
+#[cfg(all(test, feature = "linked_list"))]
+mod tests {
+    use core::{
+        alloc::{Allocator as _, GlobalAlloc, Layout},
+        cell::SyncUnsafeCell,
+        ptr::{addr_of_mut, slice_from_raw_parts_mut, NonNull},
+    };
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        thread,
+        time::Duration,
+        vec::Vec,
+    };
+
+    use super::{Backoff, Locked, Stats};
+    use crate::linked_list;
+
+    #[repr(align(8))]
+    struct MemPool<const N: usize>([u8; N]);
+
+    #[test]
+    fn handoff() {
+        // a single block, so that both threads keep reusing it
+        const HEAP_SIZE: usize = 64;
+        const SENTINEL: u64 = 0x5a5a_5a5a_5a5a_5a5a;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        static ALLOC: Locked<linked_list::Allocator> = Locked::new(linked_list::Allocator::new());
+        unsafe {
+            ALLOC.lock().add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+        }
+        let layout = Layout::new::<[u64; HEAP_SIZE / 8]>();
+        // the first two words hold the free list node while the block is free
+        let payload = 2..HEAP_SIZE / 8;
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..10_000 {
+                    let p = unsafe { ALLOC.alloc(layout) }.cast::<u64>();
+                    if p.is_null() {
+                        continue;
+                    }
+                    for i in payload.clone() {
+                        unsafe { p.add(i).write(SENTINEL) };
+                    }
+                    unsafe { ALLOC.dealloc(p.cast(), layout) };
+                }
+            });
+            s.spawn(|| {
+                for _ in 0..10_000 {
+                    let p = unsafe { ALLOC.alloc(layout) }.cast::<u64>();
+                    if p.is_null() {
+                        continue;
+                    }
+                    // either the writer never had the block yet, or all of its
+                    // writes are visible
+                    let first = unsafe { p.add(payload.start).read() };
+                    assert!(first == 0 || first == SENTINEL);
+                    for i in payload.clone() {
+                        assert_eq!(unsafe { p.add(i).read() }, first);
+                    }
+                    unsafe { ALLOC.dealloc(p.cast(), layout) };
+                }
+            });
+        });
+    }
+
+    #[test]
+    fn vec_grow() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let alloc = Locked::new(linked_list::Allocator::new());
+        unsafe {
+            alloc.lock().add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+        }
+        // a 5 byte request is padded to a whole free list node
+        let p = alloc.allocate(Layout::new::<[u8; 5]>()).unwrap();
+        assert_eq!(p.len(), 16);
+        unsafe { alloc.deallocate(p.as_non_null_ptr(), Layout::new::<[u8; 5]>()) };
+
+        let mut v = Vec::<u8, _>::with_capacity_in(5, &alloc);
+        v.extend(0..5);
+        // something right after the vector, so that it has to move to grow
+        let blocker = Vec::<u8, _>::with_capacity_in(1, &alloc);
+        v.reserve(40);
+        assert!(v.capacity() >= 45);
+        assert_eq!(v, [0, 1, 2, 3, 4]);
+        v.extend(5..45);
+        assert!(v.iter().copied().eq(0..45));
+        drop(blocker);
+        drop(v);
+        assert_eq!(alloc.stats().live_bytes, 0);
+        assert!(alloc.into_inner().is_empty());
+    }
+
+    #[test]
+    fn stats_fitted_layout() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let alloc = Locked::new(linked_list::Allocator::new());
+        unsafe {
+            alloc.lock().add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+        }
+        let layout = Layout::new::<[u8; 5]>();
+        let p = alloc.allocate(layout).unwrap();
+        assert_eq!(alloc.stats().live_bytes, 16);
+        // any layout between the one asked for and the block handed out fits
+        let fitted = Layout::from_size_align(p.len(), layout.align()).unwrap();
+        unsafe { alloc.deallocate(p.as_non_null_ptr(), fitted) };
+        assert_eq!(alloc.stats().live_bytes, 0);
+
+        let p = alloc.allocate(layout).unwrap();
+        let grown = Layout::new::<[u8; 40]>();
+        let p = unsafe { alloc.grow(p.as_non_null_ptr(), fitted, grown) }.unwrap();
+        assert_eq!(alloc.stats().live_bytes, 40);
+        let fitted = Layout::from_size_align(p.len(), grown.align()).unwrap();
+        unsafe { alloc.deallocate(p.as_non_null_ptr(), fitted) };
+        assert_eq!(
+            alloc.stats(),
+            Stats {
+                allocs: 2,
+                frees: 2,
+                live_bytes: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn backoff() {
+        const HEAP_SIZE: usize = 1 << 10;
+        const THREADS: u64 = 4;
+        const ROUNDS: usize = 1000;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        static ALLOC: Locked<linked_list::Allocator> = Locked::with_backoff(
+            linked_list::Allocator::new(),
+            Backoff::Exponential { max_shift: 6 },
+        );
+        unsafe {
+            ALLOC.lock().add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+        }
+        let layout = Layout::new::<[u64; 4]>();
+
+        // a waiter always has to back off while the lock is held
+        let guard = ALLOC.lock();
+        thread::scope(|s| {
+            let waiter = s.spawn(|| drop(ALLOC.lock()));
+            thread::sleep(Duration::from_millis(10));
+            drop(guard);
+            waiter.join().unwrap();
+        });
+        assert!(ALLOC.contended() >= 1);
+
+        thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    for _ in 0..ROUNDS {
+                        let p = unsafe { ALLOC.alloc(layout) };
+                        assert!(!p.is_null());
+                        assert!(ALLOC.lock().allocation_count() <= THREADS);
+                        unsafe { ALLOC.dealloc(p, layout) };
+                    }
+                });
+            }
+        });
+        let alloc = ALLOC.lock();
+        assert!(alloc.is_empty());
+        assert_eq!(alloc.free_bytes(), HEAP_SIZE);
+    }
+
+    #[test]
+    fn stats() {
+        const HEAP_SIZE: usize = 1 << 10;
+        const THREADS: u64 = 2;
+        const ROUNDS: u64 = 1000;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        static ALLOC: Locked<linked_list::Allocator> = Locked::new(linked_list::Allocator::new());
+        static DONE: AtomicBool = AtomicBool::new(false);
+        unsafe {
+            ALLOC.lock().add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+        }
+        let layout = Layout::new::<[u64; 4]>();
+
+        thread::scope(|s| {
+            let monitor = s.spawn(|| {
+                let mut last = Stats::default();
+                while !DONE.load(Ordering::Relaxed) {
+                    let stats = ALLOC.stats();
+                    assert!(stats.allocs >= last.allocs && stats.frees >= last.frees);
+                    assert!(stats.live_bytes <= THREADS * 32);
+                    last = stats;
+                }
+            });
+            let workers: Vec<_> = (0..THREADS)
+                .map(|_| {
+                    s.spawn(|| {
+                        for _ in 0..ROUNDS {
+                            let p = unsafe { ALLOC.alloc(layout) };
+                            assert!(!p.is_null());
+                            unsafe { ALLOC.dealloc(p, layout) };
+                        }
+                    })
+                })
+                .collect();
+            for worker in workers {
+                worker.join().unwrap();
+            }
+            DONE.store(true, Ordering::Relaxed);
+            monitor.join().unwrap();
+        });
+        assert_eq!(
+            ALLOC.stats(),
+            Stats {
+                allocs: THREADS * ROUNDS,
+                frees: THREADS * ROUNDS,
+                live_bytes: 0,
+            }
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use core::{alloc::Layout, ptr::NonNull};
+
+// An allocator that never has any memory, for exercising the out-of-memory
+// paths of code that allocates.
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Allocator;
+
+unsafe impl super::Allocator for Allocator {
+    unsafe fn alloc(&mut self, _layout: Layout) -> Option<NonNull<[u8]>> {
+        None
+    }
+
+    unsafe fn dealloc(&mut self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{alloc::Layout, ptr::NonNull};
+
+    use super::Allocator;
+    use crate::AllocError;
+
+    /// A minimal growable stack of `u32`s.
+    struct Stack<A: crate::Allocator> {
+        alloc: A,
+        buf: Option<NonNull<[u8]>>,
+        len: usize,
+    }
+
+    impl<A: crate::Allocator> Stack<A> {
+        fn try_push(&mut self, value: u32) -> Result<(), AllocError> {
+            let capacity = self.buf.map_or(0, |buf| buf.len() / 4);
+            if self.len == capacity {
+                let layout = Layout::array::<u32>(Ord::max(2 * capacity, 1)).unwrap();
+                let buf = unsafe { self.alloc.try_alloc(layout) }?;
+                if let Some(old) = self.buf.replace(buf) {
+                    unsafe {
+                        buf.as_mut_ptr()
+                            .copy_from_nonoverlapping(old.as_mut_ptr(), old.len());
+                        self.alloc
+                            .dealloc(old.as_mut_ptr(), Layout::array::<u32>(capacity).unwrap());
+                    }
+                }
+            }
+            let buf = self.buf.unwrap().as_mut_ptr().cast::<u32>();
+            unsafe { buf.add(self.len).write_unaligned(value) };
+            self.len += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test() {
+        let mut stack = Stack {
+            alloc: Allocator,
+            buf: None,
+            len: 0,
+        };
+        assert_eq!(stack.try_push(1), Err(AllocError::OutOfMemory));
+        assert_eq!(stack.len, 0);
+        assert!(stack.buf.is_none());
+    }
+}
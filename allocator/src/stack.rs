@@ -0,0 +1,160 @@
+use core::{
+    alloc::Layout,
+    iter,
+    marker::PhantomData,
+    mem,
+    ops::Range,
+    ptr::{self, slice_from_raw_parts_mut, NonNull},
+};
+
+use ptr_ext::PtrExt;
+
+use crate::alloc_assert;
+
+// Bump allocator whose allocations are freed in LIFO order. Each allocation
+// has a header right in front of it recording the tip before it was made and
+// the allocation below it, so that freeing the top allocation rewinds the tip
+// exactly, and the live allocations can be walked from the top down.
+
+/// A LIFO bump allocator with exclusive use of its region for `'a`.
+pub struct Allocator<'a> {
+    region: NonNull<[u8]>,
+    tip: *mut u8,
+    top: Option<NonNull<Header>>,
+    _region: PhantomData<&'a mut [u8]>,
+}
+
+struct Header {
+    // the tip before the allocation was made
+    prev_tip: *mut u8,
+    // the header of the allocation below
+    prev: Option<NonNull<Header>>,
+    size: usize,
+}
+
+impl<'a> Allocator<'a> {
+    /// Creates an allocator handing out memory from `region`.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that
+    /// `region` is valid and unused by anything else for `'a`.
+    pub unsafe fn new(region: NonNull<[u8]>) -> Allocator<'a> {
+        Allocator {
+            region,
+            tip: region.as_mut_ptr(),
+            top: None,
+            _region: PhantomData,
+        }
+    }
+
+    /// Creates an allocator handing out memory from `slice`, which it
+    /// borrows for as long as it lives.
+    pub fn from_slice(slice: &'a mut [u8]) -> Allocator<'a> {
+        unsafe { Allocator::new(NonNull::from(slice)) }
+    }
+
+    /// Returns whether there are no outstanding allocations.
+    pub fn is_empty(&self) -> bool {
+        self.top.is_none()
+    }
+
+    /// Returns the range of addresses managed by this allocator.
+    pub fn region_range(&self) -> Range<usize> {
+        let start = self.region.addr().get();
+        start..start + self.region.len()
+    }
+
+    /// Returns the pointer and size of each live allocation, from the most
+    /// recent one down.
+    pub fn iter_lifo(&self) -> impl Iterator<Item = (*mut u8, usize)> + '_ {
+        let mut next = self.top;
+        iter::from_fn(move || {
+            let header = next?.as_ptr();
+            let Header { prev, size, .. } = unsafe { header.read() };
+            next = prev;
+            Some((header.wrapping_add(1).cast::<u8>(), size))
+        })
+    }
+}
+
+unsafe impl super::Allocator for Allocator<'_> {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<NonNull<[u8]>> {
+        // the header goes right in front of the allocation, so both have to
+        // be aligned for it
+        let align = Ord::max(layout.align(), mem::align_of::<Header>());
+        let alloc_start = self
+            .tip
+            .with_addr(self.tip.addr().checked_add(mem::size_of::<Header>())?)
+            .try_align_up(align)?;
+        let alloc_end = alloc_start.with_addr(alloc_start.addr().checked_add(layout.size())?);
+        if alloc_end.addr() > self.region_range().end {
+            return None;
+        }
+        let header = alloc_start.cast::<Header>().wrapping_sub(1);
+        unsafe {
+            header.write(Header {
+                prev_tip: self.tip,
+                prev: self.top,
+                size: layout.size(),
+            })
+        };
+        self.top = NonNull::new(header);
+        self.tip = alloc_end;
+        NonNull::new(slice_from_raw_parts_mut(alloc_start, layout.size()))
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, _layout: Layout) {
+        let top = self.iter_lifo().next().map(|(top, _)| top);
+        alloc_assert(top == Some(ptr), "freeing out of LIFO order");
+        let header = ptr.cast::<Header>().wrapping_sub(1);
+        let Header { prev_tip, prev, .. } = unsafe { ptr::read(header) };
+        self.tip = prev_tip;
+        self.top = prev;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+    use std::{panic, vec::Vec};
+
+    use super::Allocator;
+    use crate::Allocator as _;
+
+    #[test]
+    fn iter_lifo() {
+        let mut heap = [0u64; 32];
+        let heap = unsafe { heap.align_to_mut::<u8>().1 };
+        let start = heap.as_mut_ptr();
+        let mut alloc = Allocator::from_slice(heap);
+        let layouts = [
+            Layout::new::<u64>(),
+            Layout::new::<[u8; 5]>(),
+            Layout::from_size_align(16, 32).unwrap(),
+        ];
+        unsafe {
+            let ptrs = layouts.map(|l| alloc.alloc(l).unwrap().as_mut_ptr());
+            assert!(ptrs.iter().all(|&p| p.addr() > start.addr()));
+            assert!(ptrs[2].is_aligned_to(32));
+            let live: Vec<_> = alloc.iter_lifo().collect();
+            assert_eq!(live, [(ptrs[2], 16), (ptrs[1], 5), (ptrs[0], 8)]);
+
+            // only the top allocation can be freed
+            let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                alloc.dealloc(ptrs[1], layouts[1])
+            }));
+            assert!(result.is_err());
+
+            alloc.dealloc(ptrs[2], layouts[2]);
+            assert_eq!(alloc.iter_lifo().count(), 2);
+            // the tip went back to the end of the allocation below
+            assert_eq!(alloc.alloc(layouts[2]).unwrap().as_mut_ptr(), ptrs[2]);
+            for (&p, &l) in ptrs.iter().zip(&layouts).rev() {
+                alloc.dealloc(p, l);
+            }
+        }
+        assert!(alloc.is_empty());
+        assert_eq!(alloc.tip, start);
+    }
+}
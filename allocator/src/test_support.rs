@@ -0,0 +1,87 @@
+//! Helpers shared by the allocator tests.
+
+use core::{alloc::Layout, ops::Range, ptr::NonNull};
+use std::vec::Vec;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum OverlapError {
+    /// Two live allocations share at least one byte.
+    Overlapping(Range<usize>, Range<usize>),
+    /// An allocation is not fully contained in any of the given regions.
+    OutOfRegion(Range<usize>),
+}
+
+fn range_of(alloc: NonNull<[u8]>, layout: Layout) -> Range<usize> {
+    let start = alloc.as_mut_ptr().addr();
+    start..start + layout.size()
+}
+
+/// Checks that no two of the given live allocations overlap.
+pub fn check_non_overlapping(allocs: &[(NonNull<[u8]>, Layout)]) -> Result<(), OverlapError> {
+    let mut ranges = allocs
+        .iter()
+        .map(|&(alloc, layout)| range_of(alloc, layout))
+        .filter(|range| !range.is_empty())
+        .collect::<Vec<_>>();
+    ranges.sort_by_key(|range| range.start);
+    for pair in ranges.windows(2) {
+        if pair[1].start < pair[0].end {
+            return Err(OverlapError::Overlapping(pair[0].clone(), pair[1].clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that every given allocation lies entirely within one of `regions`.
+pub fn check_within(
+    allocs: &[(NonNull<[u8]>, Layout)],
+    regions: &[NonNull<[u8]>],
+) -> Result<(), OverlapError> {
+    for &(alloc, layout) in allocs {
+        let range = range_of(alloc, layout);
+        let contained = regions.iter().any(|region| {
+            let start = region.as_mut_ptr().addr();
+            start <= range.start && range.end <= start + region.len()
+        });
+        if !contained {
+            return Err(OverlapError::OutOfRegion(range));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        alloc::Layout,
+        ptr::{self, NonNull},
+    };
+
+    use super::{check_non_overlapping, check_within, OverlapError};
+
+    fn slice(addr: usize, len: usize) -> NonNull<[u8]> {
+        NonNull::new(ptr::slice_from_raw_parts_mut(
+            ptr::null_mut::<u8>().with_addr(addr),
+            len,
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test() {
+        let l = Layout::from_size_align(16, 8).unwrap();
+        let valid = [(slice(0x1010, 16), l), (slice(0x1000, 16), l)];
+        assert_eq!(check_non_overlapping(&valid), Ok(()));
+        assert_eq!(check_within(&valid, &[slice(0x1000, 0x20)]), Ok(()));
+        assert_eq!(
+            check_within(&valid, &[slice(0x1000, 0x18)]),
+            Err(OverlapError::OutOfRegion(0x1010..0x1020))
+        );
+
+        let overlapping = [(slice(0x1008, 16), l), (slice(0x1000, 16), l)];
+        assert_eq!(
+            check_non_overlapping(&overlapping),
+            Err(OverlapError::Overlapping(0x1000..0x1010, 0x1008..0x1018))
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use core::{alloc::Layout, ptr::NonNull};
+
+use crate::{alloc_assert, alloc_fail, AllocError};
+
+// Wraps another allocator and records each live allocation in a registry of
+// CAP slots, so that outstanding allocations can be listed for leak reports
+// without any dynamic memory. An allocation that would not fit in the
+// registry fails. Since the registry knows where each allocation starts, a
+// pointer into the middle of one passed to dealloc is caught, and can be
+// snapped to the start of its allocation instead.
+
+pub struct Allocator<A, const CAP: usize> {
+    inner: A,
+    entries: [Option<(NonNull<u8>, Layout)>; CAP],
+    len: usize,
+    lenient_dealloc: bool,
+}
+
+impl<A, const CAP: usize> Allocator<A, CAP> {
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            entries: [None; CAP],
+            len: 0,
+            lenient_dealloc: false,
+        }
+    }
+
+    /// Sets whether `dealloc` frees the allocation containing an interior
+    /// pointer rather than failing an assertion.
+    pub fn set_lenient_dealloc(&mut self, lenient: bool) {
+        self.lenient_dealloc = lenient;
+    }
+
+    pub fn lenient_dealloc(&self) -> bool {
+        self.lenient_dealloc
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+
+    /// Returns the number of live allocations.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether there are no live allocations.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns whether the registry has no free slots left, so that further
+    /// allocations fail until something is freed.
+    pub fn is_full(&self) -> bool {
+        self.len == CAP
+    }
+
+    /// Returns an iterator over the pointer and layout of each live
+    /// allocation.
+    pub fn live(&self) -> impl Iterator<Item = (NonNull<u8>, Layout)> + '_ {
+        self.entries.iter().flatten().copied()
+    }
+
+    /// Returns the layout of the live allocation at `ptr`, if there is one.
+    pub fn layout_of(&self, ptr: *const u8) -> Option<Layout> {
+        self.live()
+            .find(|&(live, _)| live.as_ptr().cast_const() == ptr)
+            .map(|(_, layout)| layout)
+    }
+
+    /// Returns the start and layout of the live allocation containing `ptr`.
+    fn containing(&self, ptr: *mut u8) -> Option<(*mut u8, Layout)> {
+        self.live()
+            .find(|(start, layout)| {
+                let offset = ptr.addr().wrapping_sub(start.addr().get());
+                offset < Ord::max(layout.size(), 1)
+            })
+            .map(|(start, layout)| (start.as_ptr(), layout))
+    }
+
+    fn slot_of(&mut self, ptr: *mut u8) -> Option<&mut Option<(NonNull<u8>, Layout)>> {
+        self.entries
+            .iter_mut()
+            .find(|entry| matches!(entry, Some((live, _)) if live.as_ptr() == ptr))
+    }
+
+    fn untrack(&mut self, ptr: *mut u8) {
+        if let Some(slot) = self.slot_of(ptr) {
+            *slot = None;
+            self.len -= 1;
+        }
+    }
+
+    fn resized(&mut self, ptr: *mut u8, new_size: usize) {
+        if let Some(Some((_, layout))) = self.slot_of(ptr) {
+            if let Ok(new_layout) = Layout::from_size_align(new_size, layout.align()) {
+                *layout = new_layout;
+            }
+        }
+    }
+}
+
+unsafe impl<A: super::Allocator, const CAP: usize> super::Allocator for Allocator<A, CAP> {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<NonNull<[u8]>> {
+        unsafe { self.try_alloc(layout) }.ok()
+    }
+
+    unsafe fn try_alloc(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let Some(slot) = self.entries.iter().position(Option::is_none) else {
+            return Err(AllocError::OutOfMemory);
+        };
+        let ptr = unsafe { self.inner.try_alloc(layout) }?;
+        self.entries[slot] = Some((ptr.as_non_null_ptr(), layout));
+        self.len += 1;
+        Ok(ptr)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let (ptr, layout) = match self.containing(ptr) {
+            Some((start, _)) if start == ptr => (ptr, layout),
+            Some(containing) => {
+                alloc_assert(self.lenient_dealloc, "interior pointer passed to dealloc");
+                containing
+            }
+            None => alloc_fail("freeing an untracked allocation"),
+        };
+        self.untrack(ptr);
+        unsafe { self.inner.dealloc(ptr, layout) };
+    }
+
+    unsafe fn dealloc_unchecked(&mut self, ptr: *mut u8, layout: Layout) {
+        self.untrack(ptr);
+        unsafe { self.inner.dealloc_unchecked(ptr, layout) };
+    }
+
+    unsafe fn grow_in_place(&mut self, ptr: *mut u8, layout: Layout, new_size: usize) -> bool {
+        let grown = unsafe { self.inner.grow_in_place(ptr, layout, new_size) };
+        if grown {
+            self.resized(ptr, new_size);
+        }
+        grown
+    }
+
+    unsafe fn shrink_in_place(&mut self, ptr: *mut u8, layout: Layout, new_size: usize) -> bool {
+        let shrunk = unsafe { self.inner.shrink_in_place(ptr, layout, new_size) };
+        if shrunk {
+            self.resized(ptr, new_size);
+        }
+        shrunk
+    }
+}
+
+#[cfg(all(test, feature = "linked_list"))]
+mod tests {
+    use core::{
+        alloc::Layout,
+        cell::SyncUnsafeCell,
+        ptr::{addr_of_mut, slice_from_raw_parts_mut, NonNull},
+    };
+    use std::{panic, vec::Vec};
+
+    use super::Allocator;
+    use crate::{linked_list, AllocError, Allocator as _};
+
+    #[repr(align(8))]
+    struct MemPool<const N: usize>([u8; N]);
+
+    #[test]
+    fn test() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let mut inner = linked_list::Allocator::new();
+        unsafe {
+            inner.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+        }
+        let mut alloc = Allocator::<_, 3>::new(inner);
+        let l1 = Layout::new::<u64>();
+        let l2 = Layout::new::<[u64; 2]>();
+        unsafe {
+            let a = alloc.alloc(l1).unwrap().as_non_null_ptr();
+            let b = alloc.alloc(l2).unwrap().as_non_null_ptr();
+            let c = alloc.alloc(l1).unwrap().as_non_null_ptr();
+            assert!(alloc.is_full());
+            // the heap has room, but the registry does not
+            assert_eq!(alloc.try_alloc(l1), Err(AllocError::OutOfMemory));
+
+            let mut live: Vec<_> = alloc.live().collect();
+            live.sort_by_key(|(ptr, _)| ptr.addr());
+            let mut expected = [(a, l1), (b, l2), (c, l1)];
+            expected.sort_by_key(|(ptr, _)| ptr.addr());
+            assert_eq!(live, expected);
+            assert_eq!(alloc.layout_of(b.as_ptr()), Some(l2));
+
+            alloc.dealloc(b.as_ptr(), l2);
+            assert_eq!(alloc.len(), 2);
+            assert_eq!(alloc.layout_of(b.as_ptr()), None);
+            let d = alloc.alloc(l2).unwrap().as_non_null_ptr();
+            assert_eq!(alloc.layout_of(d.as_ptr()), Some(l2));
+            for (ptr, layout) in [(a, l1), (c, l1), (d, l2)] {
+                alloc.dealloc(ptr.as_ptr(), layout);
+            }
+        }
+        assert!(alloc.is_empty());
+        assert!(alloc.into_inner().is_empty());
+    }
+
+    #[test]
+    fn interior_pointers() {
+        const HEAP_SIZE: usize = 1 << 7;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let mut inner = linked_list::Allocator::new();
+        unsafe {
+            inner.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+        }
+        let mut alloc = Allocator::<_, 4>::new(inner);
+        let l = Layout::new::<[u64; 4]>();
+        unsafe {
+            let a = alloc.alloc(l).unwrap().as_mut_ptr();
+            let b = alloc.alloc(l).unwrap().as_mut_ptr();
+            alloc.dealloc(a, l);
+            assert_eq!(alloc.len(), 1);
+
+            // strict by default
+            let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                alloc.dealloc(b.wrapping_add(8), Layout::new::<u64>())
+            }));
+            assert!(result.is_err());
+            assert_eq!(alloc.layout_of(b), Some(l));
+
+            alloc.set_lenient_dealloc(true);
+            alloc.dealloc(b.wrapping_add(8), Layout::new::<u64>());
+        }
+        assert!(alloc.is_empty());
+        let inner = alloc.into_inner();
+        assert!(inner.is_empty());
+        assert_eq!(inner.free_bytes(), HEAP_SIZE);
+    }
+}
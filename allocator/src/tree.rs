@@ -0,0 +1,546 @@
+use core::{
+    alloc::Layout,
+    cmp::Ordering,
+    mem,
+    ptr::{self, NonNull},
+};
+
+use ptr_ext::PtrExt;
+
+use crate::{alloc_assert, alloc_fail};
+
+// Best-fit allocator for heaps with many free blocks. The free blocks are
+// indexed by two AVL trees whose nodes are stored in the free blocks
+// themselves. One is ordered by (size, address), so finding the best fit is
+// O(log n) rather than a scan of the whole free list. The other is ordered by
+// address, so that freed memory is merged with the free blocks right before
+// and after it in O(log n) as well.
+
+type Link = Option<NonNull<Node>>;
+
+/// Which of the two trees a node is linked into through which of its
+/// [`Links`].
+#[derive(Clone, Copy)]
+enum Index {
+    /// Ordered by (size, address), for finding the best fit.
+    Size,
+    /// Ordered by address, for finding the neighbours of freed memory.
+    Addr,
+}
+
+pub struct Allocator {
+    by_size: Link,
+    by_addr: Link,
+}
+
+impl Allocator {
+    /// Creates an empty Allocator.
+    pub const fn new() -> Self {
+        Self {
+            by_size: None,
+            by_addr: None,
+        }
+    }
+
+    /// Adds the given memory region to the tree.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that the given
+    /// memory region is valid and unused.
+    pub unsafe fn add_free_region(&mut self, region: NonNull<[u8]>) {
+        alloc_assert(
+            region.as_mut_ptr().is_aligned_to(mem::align_of::<Node>())
+                && region.len() >= mem::size_of::<Node>(),
+            "invalid free region",
+        );
+
+        let node_ptr = region.cast::<Node>();
+        unsafe {
+            node_ptr.as_ptr().write(Node {
+                size: region.len(),
+                by_size: Links::DETACHED,
+                by_addr: Links::DETACHED,
+            });
+        }
+        self.by_size = Some(Node::insert(Index::Size, self.by_size, node_ptr));
+        self.by_addr = Some(Node::insert(Index::Addr, self.by_addr, node_ptr));
+    }
+
+    /// Unlinks the free region `node` from both trees.
+    fn remove(&mut self, node: NonNull<Node>) {
+        self.by_size = Node::remove(Index::Size, self.by_size, Node::key(Index::Size, node));
+        self.by_addr = Node::remove(Index::Addr, self.by_addr, Node::key(Index::Addr, node));
+    }
+
+    /// Looks for the smallest free region that can hold an allocation with the
+    /// given size and alignment and removes it from the tree. Ties are broken
+    /// by address.
+    ///
+    /// Returns a tuple of the tree node and a slice pointing to the allocation
+    fn find_region(&mut self, layout: Layout) -> Option<(NonNull<Node>, NonNull<[u8]>)> {
+        let mut key = (layout.size(), 0);
+        while let Some(region) = Node::lower_bound(Index::Size, self.by_size, key) {
+            if let Some(alloc) = Node::alloc_from_region(region.as_ptr(), layout) {
+                self.remove(region);
+                return Some((region, alloc));
+            }
+            key = (key_size(region), region.addr().get() + 1);
+        }
+        None
+    }
+
+    /// Adjust the given layout so that the resulting allocated memory
+    /// region is also capable of storing a `Node`.
+    fn adjust(layout: Layout) -> Layout {
+        let layout = layout
+            .align_to(mem::align_of::<Node>())
+            .unwrap_or_else(|_| alloc_fail("adjusting alignment failed"))
+            .pad_to_align();
+        Layout::from_size_align(
+            Ord::max(layout.size(), mem::size_of::<Node>()),
+            layout.align(),
+        )
+        .unwrap()
+    }
+}
+
+impl Default for Allocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl super::Allocator for Allocator {
+    unsafe fn alloc(&mut self, layout: Layout) -> Option<NonNull<[u8]>> {
+        let layout = Allocator::adjust(layout);
+        self.find_region(layout).map(|(region, alloc)| {
+            let alloc_end = alloc
+                .as_ptr()
+                .as_mut_ptr()
+                .map_addr(|addr| addr + alloc.len());
+            let excess_size = Node::end(region.as_ptr()).addr() - alloc_end.addr();
+            if excess_size > 0 {
+                unsafe {
+                    // SAFETY: alloc has provenance for entire memory region pointed to by region
+                    self.add_free_region(
+                        NonNull::new(ptr::slice_from_raw_parts_mut(alloc_end, excess_size))
+                            .unwrap(),
+                    );
+                }
+            }
+            alloc
+        })
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let layout = Allocator::adjust(layout);
+        let (mut start, mut size) = (ptr, layout.size());
+        let end = ptr.addr() + size;
+        if let Some(next) = Node::lower_bound(Index::Addr, self.by_addr, (end, 0))
+            .filter(|next| next.addr().get() == end)
+        {
+            self.remove(next);
+            size += key_size(next);
+        }
+        if let Some(prev) = Node::last_below(Index::Addr, self.by_addr, (ptr.addr(), 0))
+            .filter(|prev| Node::end(prev.as_ptr()).addr() == ptr.addr())
+        {
+            self.remove(prev);
+            start = prev.as_ptr().cast();
+            size += key_size(prev);
+        }
+        unsafe {
+            self.add_free_region(NonNull::new(ptr::slice_from_raw_parts_mut(start, size)).unwrap());
+        }
+    }
+}
+
+fn key_size(node: NonNull<Node>) -> usize {
+    unsafe { (*node.as_ptr()).size }
+}
+
+fn height(index: Index, link: Link) -> usize {
+    link.map_or(0, |node| unsafe { (*Node::links(node, index)).height })
+}
+
+// node: Node is the header of a free memory region of size node.size >=
+// size_of::<Node>() bytes, and through each of its Links the root of an AVL
+// subtree whose left descendants have smaller keys in that index and right
+// descendants larger ones
+struct Node {
+    size: usize,
+    by_size: Links,
+    by_addr: Links,
+}
+
+struct Links {
+    left: Link,
+    right: Link,
+    height: usize,
+}
+
+impl Links {
+    const DETACHED: Links = Links {
+        left: None,
+        right: None,
+        height: 1,
+    };
+}
+
+impl Node {
+    fn key(index: Index, this: NonNull<Node>) -> (usize, usize) {
+        match index {
+            Index::Size => (key_size(this), this.addr().get()),
+            Index::Addr => (this.addr().get(), 0),
+        }
+    }
+
+    fn links(this: NonNull<Node>, index: Index) -> *mut Links {
+        let p = this.as_ptr();
+        unsafe {
+            match index {
+                Index::Size => ptr::addr_of_mut!((*p).by_size),
+                Index::Addr => ptr::addr_of_mut!((*p).by_addr),
+            }
+        }
+    }
+
+    fn end(this: *mut Node) -> *mut u8 {
+        this.cast::<u8>()
+            .map_addr(|addr| addr + unsafe { (*this).size })
+    }
+
+    fn alloc_from_region(this: *mut Self, layout: Layout) -> Option<NonNull<[u8]>> {
+        let alloc_start = this.cast::<u8>().try_align_up(layout.align())?;
+        let alloc_end = alloc_start.with_addr(alloc_start.addr().checked_add(layout.size())?);
+
+        if alloc_end > Node::end(this) {
+            return None;
+        }
+
+        let excess_size = Node::end(this).addr() - alloc_end.addr();
+        if 0 < excess_size && excess_size < mem::size_of::<Node>() {
+            return None;
+        }
+
+        NonNull::new(ptr::slice_from_raw_parts_mut(alloc_start, layout.size()))
+    }
+
+    /// Returns the node with the smallest key not less than `key`.
+    fn lower_bound(index: Index, mut link: Link, key: (usize, usize)) -> Link {
+        let mut found = None;
+        while let Some(node) = link {
+            let links = Node::links(node, index);
+            if Node::key(index, node) >= key {
+                found = Some(node);
+                link = unsafe { (*links).left };
+            } else {
+                link = unsafe { (*links).right };
+            }
+        }
+        found
+    }
+
+    /// Returns the node with the largest key less than `key`.
+    fn last_below(index: Index, mut link: Link, key: (usize, usize)) -> Link {
+        let mut found = None;
+        while let Some(node) = link {
+            let links = Node::links(node, index);
+            if Node::key(index, node) < key {
+                found = Some(node);
+                link = unsafe { (*links).right };
+            } else {
+                link = unsafe { (*links).left };
+            }
+        }
+        found
+    }
+
+    fn update(index: Index, this: NonNull<Node>) {
+        let l = Node::links(this, index);
+        unsafe {
+            (*l).height = 1 + Ord::max(height(index, (*l).left), height(index, (*l).right));
+        }
+    }
+
+    fn rotate_left(index: Index, this: NonNull<Node>) -> NonNull<Node> {
+        let p = Node::links(this, index);
+        unsafe {
+            let right = (*p).right.unwrap();
+            let r = Node::links(right, index);
+            (*p).right = (*r).left;
+            Node::update(index, this);
+            (*r).left = Some(this);
+            Node::update(index, right);
+            right
+        }
+    }
+
+    fn rotate_right(index: Index, this: NonNull<Node>) -> NonNull<Node> {
+        let p = Node::links(this, index);
+        unsafe {
+            let left = (*p).left.unwrap();
+            let l = Node::links(left, index);
+            (*p).left = (*l).right;
+            Node::update(index, this);
+            (*l).right = Some(this);
+            Node::update(index, left);
+            left
+        }
+    }
+
+    /// Restores the AVL invariant at `this`, whose subtrees' heights differ by
+    /// at most two, and returns the new subtree root.
+    fn balance(index: Index, this: NonNull<Node>) -> NonNull<Node> {
+        Node::update(index, this);
+        let p = Node::links(this, index);
+        unsafe {
+            let (left, right) = ((*p).left, (*p).right);
+            if height(index, left) > height(index, right) + 1 {
+                let left = left.unwrap();
+                let l = Node::links(left, index);
+                if height(index, (*l).left) < height(index, (*l).right) {
+                    (*p).left = Some(Node::rotate_left(index, left));
+                }
+                Node::rotate_right(index, this)
+            } else if height(index, right) > height(index, left) + 1 {
+                let right = right.unwrap();
+                let r = Node::links(right, index);
+                if height(index, (*r).right) < height(index, (*r).left) {
+                    (*p).right = Some(Node::rotate_right(index, right));
+                }
+                Node::rotate_left(index, this)
+            } else {
+                this
+            }
+        }
+    }
+
+    /// Inserts the detached node `node` into the subtree at `link` and returns
+    /// the new subtree root.
+    fn insert(index: Index, link: Link, node: NonNull<Node>) -> NonNull<Node> {
+        let Some(root) = link else {
+            return node;
+        };
+        let p = Node::links(root, index);
+        unsafe {
+            if Node::key(index, node) < Node::key(index, root) {
+                (*p).left = Some(Node::insert(index, (*p).left, node));
+            } else {
+                (*p).right = Some(Node::insert(index, (*p).right, node));
+            }
+        }
+        Node::balance(index, root)
+    }
+
+    /// Removes the node with key `key` from the subtree at `link` and returns
+    /// the new subtree root.
+    fn remove(index: Index, link: Link, key: (usize, usize)) -> Link {
+        let root = link?;
+        let p = Node::links(root, index);
+        unsafe {
+            match key.cmp(&Node::key(index, root)) {
+                Ordering::Less => (*p).left = Node::remove(index, (*p).left, key),
+                Ordering::Greater => (*p).right = Node::remove(index, (*p).right, key),
+                Ordering::Equal => {
+                    let (left, right) = ((*p).left, (*p).right);
+                    let Some(right) = right else {
+                        return left;
+                    };
+                    let (right, min) = Node::remove_min(index, right);
+                    let m = Node::links(min, index);
+                    (*m).left = left;
+                    (*m).right = right;
+                    return Some(Node::balance(index, min));
+                }
+            }
+        }
+        Some(Node::balance(index, root))
+    }
+
+    /// Detaches the node with the smallest key from the subtree rooted at
+    /// `root`, returning the new subtree root and the detached node.
+    fn remove_min(index: Index, root: NonNull<Node>) -> (Link, NonNull<Node>) {
+        let p = Node::links(root, index);
+        unsafe {
+            match (*p).left {
+                None => ((*p).right, root),
+                Some(left) => {
+                    let (left, min) = Node::remove_min(index, left);
+                    (*p).left = left;
+                    (Some(Node::balance(index, root)), min)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        alloc::Layout,
+        cell::SyncUnsafeCell,
+        mem,
+        ptr::{addr_of_mut, slice_from_raw_parts_mut, NonNull},
+    };
+
+    use static_assertions::const_assert_eq;
+    use std::vec::Vec;
+
+    use super::{height, key_size, Allocator, Index, Link, Node};
+    use crate::Allocator as _;
+
+    #[repr(align(8))]
+    struct MemPool<const N: usize>([u8; N]);
+    const_assert_eq!(mem::align_of::<MemPool<1>>(), mem::align_of::<Node>());
+
+    /// Checks the ordering and balance of the subtree at `link`, returning its
+    /// height.
+    fn check(index: Index, link: Link, lo: (usize, usize), hi: (usize, usize)) -> usize {
+        let Some(node) = link else {
+            return 0;
+        };
+        let key = Node::key(index, node);
+        assert!(lo <= key && key <= hi);
+        let links = Node::links(node, index);
+        let (left, right) = unsafe { ((*links).left, (*links).right) };
+        let (hl, hr) = (check(index, left, lo, key), check(index, right, key, hi));
+        assert!(hl <= hr + 1 && hr <= hl + 1);
+        assert_eq!(height(index, link), 1 + Ord::max(hl, hr));
+        height(index, link)
+    }
+
+    /// Checks both trees, which have to hold the same free blocks.
+    fn check_trees(alloc: &Allocator) {
+        let (lo, hi) = ((0, 0), (usize::MAX, usize::MAX));
+        check(Index::Size, alloc.by_size, lo, hi);
+        check(Index::Addr, alloc.by_addr, lo, hi);
+        let [mut by_size, by_addr] = [(Index::Size, alloc.by_size), (Index::Addr, alloc.by_addr)]
+            .map(|(index, root)| {
+                let mut blocks = Vec::new();
+                collect(index, root, &mut blocks);
+                blocks
+            });
+        by_size.sort_unstable();
+        assert_eq!(by_size, by_addr);
+    }
+
+    fn collect(index: Index, link: Link, blocks: &mut Vec<(usize, usize)>) {
+        if let Some(node) = link {
+            let links = Node::links(node, index);
+            let (left, right) = unsafe { ((*links).left, (*links).right) };
+            collect(index, left, blocks);
+            blocks.push((node.addr().get(), key_size(node)));
+            collect(index, right, blocks);
+        }
+    }
+
+    fn free_bytes(alloc: &Allocator) -> usize {
+        let mut blocks = Vec::new();
+        collect(Index::Size, alloc.by_size, &mut blocks);
+        blocks.iter().map(|&(_, size)| size).sum()
+    }
+
+    #[test]
+    fn test() {
+        const HEAP_SIZE: usize = 1 << 14;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let mut alloc = Allocator::new();
+
+        // free blocks of 64, 128, ..., 1024 bytes, in shuffled order
+        let mut starts = [0; 16];
+        let mut offset = 0;
+        for i in [7, 2, 12, 0, 15, 9, 4, 11, 1, 14, 6, 3, 10, 13, 5, 8] {
+            let size = (i + 1) * 64;
+            starts[i] = heap.addr() + offset;
+            unsafe {
+                alloc.add_free_region(
+                    NonNull::new(slice_from_raw_parts_mut(heap.wrapping_add(offset), size))
+                        .unwrap(),
+                );
+            }
+            offset += size + 64;
+            check_trees(&alloc);
+        }
+
+        // best fit picks the smallest sufficient block
+        let l = Layout::from_size_align(125, 8).unwrap();
+        let p = unsafe { alloc.alloc(l) }.unwrap();
+        assert_eq!(p.as_mut_ptr().addr(), starts[1]);
+        check_trees(&alloc);
+
+        // many allocations and frees keep the tree ordered and balanced
+        let mut live: [Option<(NonNull<[u8]>, Layout)>; 32] = [None; 32];
+        for round in 0..256 {
+            let slot = (round * 7) % live.len();
+            let layout = Layout::from_size_align(8 + (round * 13) % 200, 8).unwrap();
+            unsafe {
+                match live[slot].take() {
+                    Some((p, layout)) => alloc.dealloc(p.as_mut_ptr(), layout),
+                    None => live[slot] = alloc.alloc(layout).map(|p| (p, layout)),
+                }
+            }
+            check_trees(&alloc);
+        }
+
+        // and lose no free blocks along the way
+        for (p, layout) in live.into_iter().flatten() {
+            unsafe { alloc.dealloc(p.as_mut_ptr(), layout) };
+        }
+        unsafe { alloc.dealloc(p.as_mut_ptr(), l) };
+        assert_eq!(free_bytes(&alloc), (1..=16).sum::<usize>() * 64);
+    }
+
+    #[test]
+    fn overaligned_small_layout() {
+        #[repr(align(64))]
+        struct MemPool64<const N: usize>([u8; N]);
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool64<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool64([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let l = Layout::from_size_align(8, 64).unwrap();
+        let mut alloc = Allocator::new();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            // 8 bytes are asked for, but the padding up to the alignment is
+            // taken up as well
+            let p = alloc.alloc(l).unwrap();
+            assert_eq!((p.as_mut_ptr(), p.len()), (heap, 64));
+            let q = alloc.alloc(Layout::new::<u64>()).unwrap();
+            assert_eq!(q.as_mut_ptr(), heap.wrapping_add(64));
+        }
+        check_trees(&alloc);
+    }
+
+    #[test]
+    fn coalesce() {
+        const HEAP_SIZE: usize = 1 << 8;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let heap = unsafe { addr_of_mut!((*HEAP.get()).0) }.cast::<u8>();
+        let l = Layout::new::<[u64; 8]>();
+        let mut alloc = Allocator::new();
+        unsafe {
+            alloc.add_free_region(NonNull::new(slice_from_raw_parts_mut(heap, HEAP_SIZE)).unwrap());
+            let ptrs = [(); 4].map(|_| alloc.alloc(l).unwrap().as_mut_ptr());
+            // freed after its neighbours on both sides
+            for i in [0, 2, 1, 3] {
+                alloc.dealloc(ptrs[i], l);
+                check_trees(&alloc);
+            }
+        }
+        let root = alloc.by_size.unwrap();
+        assert_eq!(Node::key(Index::Size, root), (HEAP_SIZE, heap.addr()));
+        assert_eq!(alloc.by_addr, Some(root));
+        assert_eq!(height(Index::Size, alloc.by_size), 1);
+        assert_eq!(height(Index::Addr, alloc.by_addr), 1);
+
+        // so the whole heap can be handed out again
+        let p = unsafe { alloc.alloc(Layout::new::<[u8; HEAP_SIZE]>()) }.unwrap();
+        assert_eq!(p.as_mut_ptr(), heap);
+    }
+}
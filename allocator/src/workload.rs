@@ -0,0 +1,194 @@
+use core::{alloc::Layout, ptr::NonNull};
+
+use super::Allocator;
+
+// Drives an allocator through a seeded, reproducible sequence of allocations
+// and frees, for comparing allocators and strategies in the benchmarks and
+// exercising them in tests. Allocations are kept in a fixed number of slots,
+// so no dynamic memory is needed to track them.
+
+/// The most allocations a [`Driver`] keeps live at once.
+pub const SLOTS: usize = 64;
+
+/// The pattern of requests a [`Driver`] makes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Workload {
+    /// Mostly allocations of the same small size, with occasional frees.
+    SmallSameSize,
+    /// Allocations of sizes from 8 bytes to 1 KiB, with as many frees.
+    MixedSizes,
+    /// Every slot is freed as soon as it comes up again, so that allocations
+    /// are short-lived and the free list keeps changing.
+    Churn,
+}
+
+/// What a [`Driver`] has done so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub allocs: u64,
+    pub frees: u64,
+    pub failed: u64,
+}
+
+pub struct Driver {
+    workload: Workload,
+    // xorshift64 state, never zero
+    state: u64,
+    live: [Option<(NonNull<u8>, Layout)>; SLOTS],
+    report: Report,
+}
+
+impl Driver {
+    /// Creates a driver making the requests of `workload` in the order given
+    /// by `seed`.
+    pub const fn new(workload: Workload, seed: u64) -> Self {
+        Self {
+            workload,
+            state: if seed == 0 {
+                0x9e37_79b9_7f4a_7c15
+            } else {
+                seed
+            },
+            live: [None; SLOTS],
+            report: Report {
+                allocs: 0,
+                frees: 0,
+                failed: 0,
+            },
+        }
+    }
+
+    pub fn report(&self) -> Report {
+        self.report
+    }
+
+    /// Returns the number of allocations the driver holds.
+    pub fn live(&self) -> usize {
+        self.live.iter().flatten().count()
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+
+    /// Returns a number in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        usize::try_from(self.next() % u64::try_from(n).unwrap()).unwrap()
+    }
+
+    /// Makes the next request, either allocating into or freeing a slot.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because the caller must guarantee that
+    /// `alloc` is the allocator the driver's live allocations came from.
+    pub unsafe fn step<A: Allocator + ?Sized>(&mut self, alloc: &mut A) {
+        let slot = self.below(SLOTS);
+        let free_one_in = match self.workload {
+            Workload::SmallSameSize => 4,
+            Workload::MixedSizes => 2,
+            Workload::Churn => 1,
+        };
+        if let Some((ptr, layout)) = self.live[slot] {
+            if self.below(free_one_in) == 0 {
+                unsafe { alloc.dealloc(ptr.as_ptr(), layout) };
+                self.live[slot] = None;
+                self.report.frees += 1;
+            }
+            return;
+        }
+        let size = match self.workload {
+            Workload::SmallSameSize => 32,
+            Workload::MixedSizes => 8 << self.below(8),
+            Workload::Churn => 16 * (1 + self.below(16)),
+        };
+        let layout = Layout::from_size_align(size, 8).unwrap();
+        match unsafe { alloc.alloc(layout) } {
+            Some(ptr) => {
+                self.live[slot] = Some((ptr.as_non_null_ptr(), layout));
+                self.report.allocs += 1;
+            }
+            None => self.report.failed += 1,
+        }
+    }
+
+    /// Makes `steps` requests and returns the report so far.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe for the same reasons as [`Driver::step`].
+    pub unsafe fn run<A: Allocator + ?Sized>(&mut self, alloc: &mut A, steps: usize) -> Report {
+        for _ in 0..steps {
+            unsafe { self.step(alloc) };
+        }
+        self.report
+    }
+
+    /// Frees every allocation the driver holds.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe for the same reasons as [`Driver::step`].
+    pub unsafe fn free_all<A: Allocator + ?Sized>(&mut self, alloc: &mut A) {
+        for slot in &mut self.live {
+            if let Some((ptr, layout)) = slot.take() {
+                unsafe { alloc.dealloc(ptr.as_ptr(), layout) };
+                self.report.frees += 1;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "linked_list"))]
+mod tests {
+    use core::{
+        cell::SyncUnsafeCell,
+        ptr::{addr_of_mut, slice_from_raw_parts_mut, NonNull},
+    };
+
+    use super::{Driver, Workload};
+    use crate::linked_list;
+
+    #[repr(align(8))]
+    struct MemPool<const N: usize>([u8; N]);
+
+    #[test]
+    fn smoke() {
+        const HEAP_SIZE: usize = 1 << 16;
+        static HEAP: SyncUnsafeCell<MemPool<HEAP_SIZE>> =
+            SyncUnsafeCell::new(MemPool([0; HEAP_SIZE]));
+        let mut alloc = linked_list::Allocator::coalescing();
+        unsafe {
+            alloc.add_free_region(
+                NonNull::new(slice_from_raw_parts_mut(
+                    addr_of_mut!((*HEAP.get()).0).cast(),
+                    HEAP_SIZE,
+                ))
+                .unwrap(),
+            );
+        }
+        let mut driver = Driver::new(Workload::MixedSizes, 1);
+        let report = unsafe { driver.run(&mut alloc, 1000) };
+        assert_eq!(report.failed, 0);
+        assert_eq!(
+            report.allocs - report.frees,
+            u64::try_from(driver.live()).unwrap()
+        );
+        assert_eq!(alloc.allocation_count(), report.allocs - report.frees);
+
+        // the same seed makes the same requests
+        let mut again = Driver::new(Workload::MixedSizes, 1);
+        unsafe {
+            driver.free_all(&mut alloc);
+            assert_eq!(again.run(&mut alloc, 1000), report);
+            again.free_all(&mut alloc);
+        }
+        assert!(alloc.is_empty());
+        assert_eq!(alloc.free_block_count(), 1);
+    }
+}
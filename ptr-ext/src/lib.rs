@@ -1,6 +1,8 @@
 #![no_std]
 #![feature(strict_provenance)]
 
+use core::ptr::{self, NonNull};
+
 pub trait PtrExt: Sized {
     fn try_align_up(self, align: usize) -> Option<Self>;
 }
@@ -10,10 +12,120 @@ impl PtrExt for *mut u8 {
         if !align.is_power_of_two() {
             return None;
         }
-        Some(if self.addr() % align == 0 {
+        // a mask rather than `%`, which some targets turn into a division
+        // even though `align` is a power of two
+        Some(if self.addr() & (align - 1) == 0 {
             self
         } else {
             self.with_addr((self.addr() | (align - 1)).checked_add(1)?)
         })
     }
 }
+
+/// A region split in two, front part first.
+pub type Split = (NonNull<[u8]>, NonNull<[u8]>);
+
+/// Splits `region` into the bytes in front of `mid` and the rest, or returns
+/// `None` if `mid` is past its end. Either part can be empty.
+pub fn split_at(region: NonNull<[u8]>, mid: usize) -> Option<Split> {
+    let rest = region.len().checked_sub(mid)?;
+    let start = region.cast::<u8>();
+    Some((slice(start, mid), slice(offset(start, mid), rest)))
+}
+
+/// Splits off the bytes of `region` in front of the first address aligned to
+/// `align`, returning the aligned rest and how many bytes were skipped. If no
+/// address in `region` is aligned enough, the rest is empty and every byte is
+/// skipped.
+///
+/// Panics if `align` is not a power of two.
+pub fn split_aligned(region: NonNull<[u8]>, align: usize) -> (NonNull<[u8]>, usize) {
+    assert!(align.is_power_of_two(), "alignment not a power of two");
+    let start = region.cast::<u8>().as_ptr();
+    let lead = match start.try_align_up(align) {
+        Some(aligned) => Ord::min(aligned.addr() - start.addr(), region.len()),
+        None => region.len(),
+    };
+    let (_, rest) = split_at(region, lead).expect("lead within the region");
+    (rest, lead)
+}
+
+/// Returns the pointer `count` bytes past `start`, which must stay within the
+/// same region.
+fn offset(start: NonNull<u8>, count: usize) -> NonNull<u8> {
+    // a region cannot wrap around the end of the address space, so the
+    // pointer cannot become null
+    NonNull::new(start.as_ptr().wrapping_add(count)).expect("offset within the region")
+}
+
+fn slice(start: NonNull<u8>, len: usize) -> NonNull<[u8]> {
+    NonNull::new(ptr::slice_from_raw_parts_mut(start.as_ptr(), len)).expect("non-null start")
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ptr::{self, NonNull};
+
+    use super::{split_aligned, split_at, PtrExt};
+
+    #[test]
+    fn try_align_up() {
+        let at = |addr| ptr::null_mut::<u8>().with_addr(addr);
+        for (addr, align, expected) in [
+            (0, 1, Some(0)),
+            (64, 16, Some(64)),
+            (65, 16, Some(80)),
+            (79, 16, Some(80)),
+            (4095, 4096, Some(4096)),
+            (usize::MAX, 1, Some(usize::MAX)),
+            (usize::MAX - 7, 8, Some(usize::MAX - 7)),
+            (usize::MAX - 6, 8, None),
+            (64, 0, None),
+            (64, 24, None),
+        ] {
+            assert_eq!(
+                at(addr).try_align_up(align).map(<*mut u8>::addr),
+                expected,
+                "{addr:#x} aligned to {align}"
+            );
+        }
+    }
+
+    #[test]
+    fn split_at_bounds() {
+        let mut buf = [0u8; 16];
+        let region = NonNull::from(&mut buf[..]);
+        let start = region.cast::<u8>().as_ptr();
+        let parts = |mid| {
+            let part = |p: NonNull<[u8]>| (p.cast::<u8>().as_ptr(), p.len());
+            split_at(region, mid).map(|(front, rest)| (part(front), part(rest)))
+        };
+        assert_eq!(parts(0), Some(((start, 0), (start, 16))));
+        assert_eq!(parts(5), Some(((start, 5), (start.wrapping_add(5), 11))));
+        assert_eq!(parts(16), Some(((start, 16), (start.wrapping_add(16), 0))));
+        assert_eq!(parts(17), None);
+    }
+
+    #[test]
+    fn split_aligned_lead() {
+        #[repr(align(16))]
+        struct Aligned([u8; 32]);
+        let mut buf = Aligned([0; 32]);
+        let start = buf.0.as_mut_ptr();
+        let region = |offset: usize, len| {
+            NonNull::new(ptr::slice_from_raw_parts_mut(
+                start.wrapping_add(offset),
+                len,
+            ))
+            .unwrap()
+        };
+        let split = |offset, len, align| {
+            let (rest, lead) = split_aligned(region(offset, len), align);
+            (rest.cast::<u8>().as_ptr(), rest.len(), lead)
+        };
+        assert_eq!(split(0, 32, 16), (start, 32, 0));
+        assert_eq!(split(3, 29, 16), (start.wrapping_add(16), 16, 13));
+        // nothing in the region is aligned enough
+        assert_eq!(split(3, 8, 16), (start.wrapping_add(11), 0, 8));
+    }
+}